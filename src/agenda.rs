@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod test;
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Local};
+use color_eyre::eyre::{self, WrapErr};
+use cron::Schedule;
+use hashbrown::HashMap;
+
+use crate::config::ResolvedTask;
+
+/// A single upcoming run of a task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fire {
+    pub time: DateTime<Local>,
+    pub task: String,
+    /// Disabled tasks are included, but won't actually run.
+    pub enabled: bool,
+}
+
+/// The upcoming runs of a set of tasks, in the order they will happen.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Agenda {
+    pub fires: Vec<Fire>,
+    /// Tasks with a cron that will never fire again.
+    pub never: Vec<String>,
+}
+
+/// Works out when each task with a cron will next run after `from`.
+///
+/// At most `limit` runs are returned, and none after `until`. Like the
+/// scheduler, crons are evaluated in the local timezone, and the times are exact
+/// as there is no jitter to account for.
+pub fn agenda(
+    tasks: &HashMap<String, ResolvedTask>,
+    from: DateTime<Local>,
+    limit: Option<usize>,
+    until: Option<DateTime<Local>>,
+) -> eyre::Result<Agenda> {
+    let mut agenda = Agenda::default();
+
+    for (id, task) in tasks {
+        let Some(cron) = &task.config.cron else {
+            continue;
+        };
+        let schedule =
+            Schedule::from_str(cron).wrap_err_with(|| format!("Invalid cron for task `{id}`"))?;
+
+        let mut upcoming = schedule.after(&from).peekable();
+        if upcoming.peek().is_none() {
+            agenda.never.push(id.clone());
+            continue;
+        }
+
+        let upcoming = upcoming.take_while(|time| until.is_none_or(|until| *time <= until));
+        let upcoming: Box<dyn Iterator<Item = _>> = match limit {
+            Some(limit) => Box::new(upcoming.take(limit)),
+            None => Box::new(upcoming),
+        };
+        agenda.fires.extend(upcoming.map(|time| Fire {
+            time,
+            task: id.clone(),
+            enabled: task.config.enabled,
+        }));
+    }
+
+    agenda
+        .fires
+        .sort_by(|a, b| a.time.cmp(&b.time).then_with(|| a.task.cmp(&b.task)));
+    if let Some(limit) = limit {
+        agenda.fires.truncate(limit);
+    }
+    agenda.never.sort();
+
+    Ok(agenda)
+}
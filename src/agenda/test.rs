@@ -0,0 +1,66 @@
+use chrono::TimeZone;
+use pretty_assertions::assert_eq;
+
+use super::*;
+use crate::config::Config;
+
+fn tasks(config: &str) -> HashMap<String, ResolvedTask> {
+    let (_, tasks) = config.parse::<Config>().unwrap().try_into().unwrap();
+    tasks
+}
+
+fn at(hour: u32, min: u32) -> DateTime<Local> {
+    Local.with_ymd_and_hms(2024, 3, 1, hour, min, 0).unwrap()
+}
+
+#[test]
+fn test_agenda() {
+    let tasks = tasks(
+        "
+        [task.hourly]
+        cron = '0 0 * * * *'
+
+        [task.half]
+        cron = '0 30 * * * *'
+        enabled = false
+
+        [task.past]
+        cron = '0 0 0 1 1 * 2000'
+
+        [task.manual]
+        cmd = 'true'
+        ",
+    );
+
+    let agenda = agenda(&tasks, at(10, 15), Some(3), None).unwrap();
+    let fires: Vec<_> = agenda
+        .fires
+        .iter()
+        .map(|f| (f.time, f.task.as_str(), f.enabled))
+        .collect();
+
+    assert_eq!(
+        fires,
+        [
+            (at(10, 30), "half", false),
+            (at(11, 0), "hourly", true),
+            (at(11, 30), "half", false),
+        ]
+    );
+    assert_eq!(agenda.never, ["past"]);
+}
+
+#[test]
+fn test_agenda_until() {
+    let tasks = tasks(
+        "
+        [task.hourly]
+        cron = '0 0 * * * *'
+        ",
+    );
+
+    let agenda = agenda(&tasks, at(10, 15), None, Some(at(13, 0))).unwrap();
+    let times: Vec<_> = agenda.fires.iter().map(|f| f.time).collect();
+
+    assert_eq!(times, [at(11, 0), at(12, 0), at(13, 0)]);
+}
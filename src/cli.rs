@@ -1 +1,265 @@
+#[cfg(test)]
+mod test;
 
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeDelta};
+use clap::{Parser, Subcommand};
+
+use crate::config::EventFormat;
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// The config file to use.
+    #[arg(short, long, default_value = "servum.toml", global = true)]
+    pub config: PathBuf,
+    /// How scheduler events should be formatted, overriding the config.
+    #[arg(long, global = true)]
+    pub event_format: Option<EventFormat>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the scheduler (the default).
+    Run {
+        /// Prefix each line of output with the time and the task it came from,
+        /// like foreman, for running in a terminal or container.
+        #[arg(long)]
+        foreground: bool,
+        /// Shut down and exit unsuccessfully if an `on-start` task fails to start,
+        /// fails to become ready, or exits unsuccessfully when servum starts.
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Show the state of each task in a running instance.
+    Status {
+        /// Print the status as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Start a task now.
+    Start {
+        /// The id of the task.
+        task: String,
+    },
+    /// Stop a running task.
+    Stop {
+        /// The id of the task.
+        task: String,
+    },
+    /// Stop a task if it is running, then start it again.
+    Restart {
+        /// The id of the task.
+        task: String,
+    },
+    /// Enable a task until the config next changes.
+    Enable {
+        /// The id of the task.
+        task: String,
+    },
+    /// Disable a task (stopping it if running) until the config next changes.
+    Disable {
+        /// The id of the task.
+        task: String,
+    },
+    /// Reload the config of a running instance.
+    Reload,
+    /// Stop all tasks and exit.
+    Shutdown,
+    /// Show the recent output of a task from a running instance.
+    Logs {
+        /// The id of the task.
+        task: String,
+        /// Keep printing new output as it arrives.
+        #[arg(short, long)]
+        follow: bool,
+        /// Only show output from this run.
+        #[arg(long)]
+        run: Option<u64>,
+    },
+    /// Run a task once in the foreground, exiting with its exit code.
+    ///
+    /// If an instance is running, then the task is run by it so that it can't
+    /// overlap with itself.
+    RunOnce {
+        /// The id of the task.
+        task: String,
+        /// Run the task directly, even if an instance is running.
+        #[arg(long)]
+        local: bool,
+    },
+    /// Run a command with the same environment that a task would run with.
+    Exec {
+        /// The id of the task.
+        task: String,
+        /// The command to run, e.g. `servum exec my-task -- bash`.
+        #[arg(last = true, required = true)]
+        cmd: Vec<String>,
+    },
+    /// Print the environment that a task would run with.
+    Env {
+        /// The id of the task.
+        task: String,
+        /// Print the environment as JSON.
+        #[arg(long)]
+        json: bool,
+        /// Include where each variable came from: `parent`, `path`, or
+        /// `task:<id>` for the task whose `env` set it.
+        #[arg(long)]
+        sources: bool,
+    },
+    /// Show where each inherited setting of a task came from.
+    Explain {
+        /// The id of the task.
+        task: String,
+    },
+    /// Show when tasks will next run, according to their crons.
+    ///
+    /// Times are shown in the local timezone, which is the one crons are
+    /// evaluated in. Tasks have no timezone or jitter settings, so each run is
+    /// shown at its exact time.
+    #[command(visible_alias = "agenda")]
+    Next {
+        /// Only show this task.
+        task: Option<String>,
+        /// How many runs to show.
+        ///
+        /// Defaults to 10, unless `--until` is given.
+        #[arg(short = 'n', long)]
+        count: Option<usize>,
+        /// Only show runs up to this time, e.g. `24h` or `2024-03-01 12:00`.
+        #[arg(long, value_parser = parse_time)]
+        until: Option<DateTime<Local>>,
+    },
+    /// Show finished runs from the history.
+    History {
+        /// Only show runs of this task.
+        task: Option<String>,
+        /// Only show runs that failed.
+        #[arg(long)]
+        failed: bool,
+        /// Only show runs that started after this time, e.g. `24h` (ago) or
+        /// `2024-03-01 12:00`.
+        #[arg(long, value_parser = parse_since)]
+        since: Option<DateTime<Local>>,
+        /// Print the runs as JSON, including their output tails.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Simulate the schedule using a virtual clock, without running anything.
+    ///
+    /// Every task is replaced by a stub that exits successfully once its
+    /// duration has passed.
+    Simulate {
+        /// When to start the simulation.
+        #[arg(long, value_parser = parse_time, default_value = "now")]
+        from: DateTime<Local>,
+        /// When to end the simulation, e.g. `2024-03-01 12:00`, or `24h` after `--from`.
+        #[arg(long)]
+        to: String,
+        /// How long a task runs for, e.g. `backup=5m`. Can be given multiple times.
+        #[arg(long = "duration", value_parser = parse_task_duration)]
+        durations: Vec<(String, TimeDelta)>,
+        /// How long tasks without a `--duration` run for.
+        #[arg(long, value_parser = parse_duration, default_value = "1s")]
+        default_duration: TimeDelta,
+    },
+}
+
+/// Parses a duration such as `500ms`, `30s`, `5m`, `24h`, `7d` or `2w`.
+pub fn parse_duration(s: &str) -> Result<TimeDelta, String> {
+    let split = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("`{s}` is missing a unit (ms, s, m, h, d or w)"))?;
+    let (n, unit) = s.split_at(split);
+    let n: i64 = n.parse().map_err(|_| format!("Invalid duration `{s}`"))?;
+
+    match unit {
+        "ms" => TimeDelta::try_milliseconds(n),
+        "s" => TimeDelta::try_seconds(n),
+        "m" => TimeDelta::try_minutes(n),
+        "h" => TimeDelta::try_hours(n),
+        "d" => TimeDelta::try_days(n),
+        "w" => TimeDelta::try_weeks(n),
+        _ => {
+            return Err(format!(
+                "Unknown unit `{unit}`, expected ms, s, m, h, d or w"
+            ))
+        }
+    }
+    .ok_or_else(|| format!("`{s}` is too long"))
+}
+
+fn parse_task_duration(s: &str) -> Result<(String, TimeDelta), String> {
+    let (task, duration) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected `<task>=<duration>`, got `{s}`"))?;
+    Ok((task.to_owned(), parse_duration(duration)?))
+}
+
+/// Parses a local time, or a time relative to now such as `30m`, `24h` or `7d`.
+pub fn parse_time(s: &str) -> Result<DateTime<Local>, String> {
+    let now = Local::now();
+    if s == "now" {
+        return Ok(now);
+    }
+    parse_time_after(s, now)
+}
+
+/// Parses a local time, or a time before now such as `30m`, `24h` or `7d`.
+pub fn parse_since(s: &str) -> Result<DateTime<Local>, String> {
+    let now = Local::now();
+    match relative(s) {
+        Some(ago) => now
+            .checked_sub_signed(parse_duration(ago)?)
+            .ok_or_else(|| format!("`{s}` is too far away")),
+        None => parse_time_after(s, now),
+    }
+}
+
+/// Parses a local time, or a time relative to `base`.
+pub fn parse_time_after(s: &str, base: DateTime<Local>) -> Result<DateTime<Local>, String> {
+    if let Some(relative) = relative(s) {
+        return base
+            .checked_add_signed(parse_duration(relative)?)
+            .ok_or_else(|| format!("`{s}` is too far away"));
+    }
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Local));
+    }
+
+    let naive = [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+    })
+    .ok_or_else(|| format!("Invalid time `{s}`"))?;
+
+    naive
+        .and_local_timezone(Local)
+        .earliest()
+        .ok_or_else(|| format!("`{s}` does not exist in the local timezone"))
+}
+
+/// The duration part of a relative time such as `24h` or `+24h`, if it is one.
+///
+/// Only digits followed by a unit count, so that times such as
+/// `2024-03-01T12:00:00Z` aren't mistaken for one.
+fn relative(s: &str) -> Option<&str> {
+    let relative = s.strip_prefix('+').unwrap_or(s);
+    let n = relative.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    (!n.is_empty() && n.len() < relative.len() && n.bytes().all(|b| b.is_ascii_digit()))
+        .then_some(relative)
+}
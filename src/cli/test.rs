@@ -0,0 +1,37 @@
+use chrono::{TimeZone, Utc};
+use pretty_assertions::assert_eq;
+
+use super::*;
+
+fn at(hour: u32) -> DateTime<Local> {
+    Local.with_ymd_and_hms(2024, 3, 1, hour, 0, 0).unwrap()
+}
+
+#[test]
+fn test_parse_time() {
+    let utc = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+
+    assert_eq!(parse_time_after("2h", at(10)), Ok(at(12)));
+    assert_eq!(parse_time_after("+2h", at(10)), Ok(at(12)));
+    assert_eq!(parse_time_after("2024-03-01 11:00", at(10)), Ok(at(11)));
+    assert_eq!(
+        parse_time("2030-01-01T00:00:00Z"),
+        Ok(utc.with_timezone(&Local))
+    );
+    assert_eq!(
+        parse_time("2030-01-01T00:00:00+00:00"),
+        Ok(utc.with_timezone(&Local))
+    );
+    assert!(parse_time("2h30").is_err());
+}
+
+#[test]
+fn test_parse_since() {
+    let utc = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+
+    assert_eq!(
+        parse_since("2024-03-01T00:00:00Z"),
+        Ok(utc.with_timezone(&Local))
+    );
+    assert!(parse_since("24h").unwrap() < Local::now());
+}
@@ -0,0 +1,44 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use chrono::{DateTime, Local};
+
+/// The source of the current time for the scheduler.
+pub trait Clock {
+    fn now(&self) -> DateTime<Local>;
+}
+
+/// The real time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+/// A clock that only moves when told to.
+///
+/// Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct VirtualClock {
+    now: Arc<Mutex<DateTime<Local>>>,
+}
+
+impl VirtualClock {
+    pub fn new(now: DateTime<Local>) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Local>) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) = now;
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> DateTime<Local> {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
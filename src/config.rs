@@ -1,11 +1,14 @@
 #[cfg(test)]
 mod test;
 
-use std::{hash::Hash, rc::Rc, str::FromStr};
+use std::{hash::Hash, path::PathBuf, rc::Rc, str::FromStr};
 
+use clap::ValueEnum;
 use color_eyre::eyre;
 use hashbrown::HashMap;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::cli;
 
 #[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case", default)]
@@ -16,6 +19,59 @@ pub struct Config {
     /// Config watcher config.
     #[serde(default)]
     pub watch: Watch,
+    /// Default output logging config for all tasks.
+    ///
+    /// Tasks can override any of these settings with their own `log` config.
+    pub log: Option<Log>,
+    /// Control socket config.
+    #[serde(default)]
+    pub control: Control,
+    /// Scheduler event log config.
+    #[serde(default)]
+    pub events: Events,
+    /// Run history config.
+    #[serde(default)]
+    pub history: History,
+    /// The most tasks that can run at once. Tasks started beyond this are
+    /// queued until another task finishes.
+    ///
+    /// If not set, then there is no limit.
+    pub max_concurrent: Option<usize>,
+    /// Named limits on how many tasks can run at once, which tasks join using
+    /// `limits`.
+    #[serde(rename = "limit")]
+    pub limits: HashMap<String, Limit>,
+    /// Notifications sent for every task, in addition to their own.
+    pub notify: Vec<Notify>,
+    /// The default `mail-to` of every task.
+    pub mail_to: Option<String>,
+    /// The default `mail-on` of every task.
+    pub mail_on: Option<MailOn>,
+    /// The default `mail-from` of every task.
+    pub mail_from: Option<String>,
+    /// The default `sendmail` of every task.
+    pub sendmail: Option<MultiStr>,
+    /// The file where state that has to outlive servum is kept, such as when
+    /// each task last succeeded.
+    ///
+    /// This is only read on startup.
+    ///
+    /// Defaults to the path of the config file with a `.state` extension.
+    pub state_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Limit {
+    /// The most tasks in this limit that can run at once.
+    pub max: usize,
+}
+
+/// Every limit on how many tasks can run at once.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Concurrency {
+    pub max: Option<usize>,
+    pub limits: HashMap<String, Limit>,
 }
 
 #[allow(clippy::module_name_repetitions)]
@@ -51,7 +107,7 @@ pub struct TaskConfig {
     /// - If not set, then the process will be killed straight away without SIGINT
     ///   being sent first.
     ///
-    /// Defaults to 10 seconds (10_000).
+    /// Defaults to 10 seconds (`10_000`).
     pub stop_timeout: usize,
     /// If enabled, then this task will be run when the process first
     /// starts.
@@ -70,6 +126,433 @@ pub struct TaskConfig {
     ///
     /// Defaults to `true`.
     pub enabled: bool,
+    /// Tasks that must be running, or whose last run succeeded, for this task
+    /// to be started. Implies `after`.
+    pub requires: Vec<String>,
+    /// Tasks that this one is started after when they start together (e.g. as
+    /// `on-start` tasks), and stopped before when shutting down.
+    pub after: Vec<String>,
+    /// What to do when a task in `requires` fails.
+    ///
+    /// Defaults to `block`.
+    pub requirement_failure: RequirementFailure,
+    /// How to tell that the task has finished starting up.
+    ///
+    /// Until this check passes, the task is shown as `starting` and tasks that
+    /// require it are not started. If not set, the task is ready as soon as
+    /// its process has been started.
+    pub ready: Option<Ready>,
+    /// A check that is run periodically once the task is ready, restarting the
+    /// task if it fails too many times in a row.
+    pub health: Option<Health>,
+    /// Tasks to start when this task exits successfully.
+    ///
+    /// Tasks started this way are passed details of the run that started them
+    /// in `SERVUM_TRIGGER_TASK`, `SERVUM_TRIGGER_RUN`, `SERVUM_TRIGGER_SUCCESS`,
+    /// and `SERVUM_TRIGGER_EXIT_CODE` or `SERVUM_TRIGGER_SIGNAL`. Every task is
+    /// passed `SERVUM_TRIGGER`, which is the reason it was started (e.g. `cron`).
+    pub on_success: Vec<String>,
+    /// Tasks to start when this task exits unsuccessfully.
+    pub on_failure: Vec<String>,
+    /// Tasks to start when this task exits, whether it succeeded or not.
+    pub on_complete: Vec<String>,
+    /// Glob patterns (e.g. `/srv/incoming/**/*.csv`) of paths that start the
+    /// task when they change. Relative patterns are relative to the directory
+    /// servum was started in.
+    ///
+    /// The paths that changed are passed to the task in `SERVUM_CHANGED_PATHS`,
+    /// separated by newlines.
+    pub watch_paths: Vec<String>,
+    /// Which kinds of changes to `watch-paths` start the task.
+    ///
+    /// Defaults to `["create", "modify", "remove"]`.
+    pub watch_events: Vec<FsEvent>,
+    /// How long (in milliseconds) to wait for changes to stop before starting
+    /// the task, so that a batch of changes only starts it once. This also
+    /// applies to `restart-on-change`.
+    ///
+    /// Defaults to `500`.
+    pub watch_debounce: usize,
+    /// Glob patterns of paths (e.g. `src/**`) that restart the task when they
+    /// change, or start it if it isn't running. Paths ignored by a `.gitignore`
+    /// are skipped.
+    ///
+    /// This is mostly useful during development, to pick up changes to the
+    /// source of a long-running task.
+    pub restart_on_change: Vec<String>,
+    /// Named limits (from `[limit.<name>]`) that this task counts towards.
+    pub limits: Vec<String>,
+    /// Queued tasks with a higher priority are started first. Tasks with the
+    /// same priority are started in the order they were queued.
+    ///
+    /// Defaults to `0`.
+    pub priority: i32,
+    /// How long (in milliseconds) the task can be queued for before that start
+    /// is dropped.
+    ///
+    /// If not set, then the task stays queued until it can be started.
+    pub queue_timeout: Option<usize>,
+    /// Tasks with the same lock never run at the same time. A task started
+    /// while another holds its lock is queued until the lock is released.
+    pub lock: Option<String>,
+    /// A file that is locked (using `flock`) while the task runs, so that it
+    /// also excludes tasks in other instances of servum using the same file.
+    ///
+    /// If the file is already locked, then the start is skipped.
+    pub lock_file: Option<PathBuf>,
+    /// Retries a failed run after a delay, instead of waiting for the next time
+    /// it is started. Runs started by `servum start` or `servum run-once` aren't
+    /// retried.
+    ///
+    /// If not set, then it is inherited from the tasks this one extends.
+    pub retry: Option<Retry>,
+    /// Exit codes that count as success.
+    ///
+    /// Defaults to `[0]`, or to every code not in `failure-codes` if that is set.
+    pub success_codes: Option<Vec<i32>>,
+    /// Exit codes that count as failure, even if they are in `success-codes`.
+    pub failure_codes: Vec<i32>,
+    /// A regex that fails a run if any line of its output matches it, whatever
+    /// its exit code.
+    ///
+    /// When this or `succeed-on-output` is set, a run doesn't finish until its
+    /// output has closed, so that every line is checked. If a process left in
+    /// the background keeps it open, the run finishes 5 seconds after exiting.
+    pub fail_on_output: Option<String>,
+    /// A regex that a line of a run's output must match for it to succeed.
+    pub succeed_on_output: Option<String>,
+    /// Notifications to send when the task has certain outcomes, such as failing.
+    ///
+    /// Like with `fail-on-output`, runs of tasks with notifications don't finish
+    /// until their output has closed (or 5 seconds after exiting), so that all
+    /// of it can be sent.
+    pub notify: Vec<Notify>,
+    /// Who to email the output of each run to, like cron's `MAILTO`. An empty
+    /// string turns off the global `mail-to` for this task.
+    ///
+    /// The email contains the lines of output kept in the `log.buffer`, so runs
+    /// wait for their output to close in the same way as with `notify`.
+    pub mail_to: Option<String>,
+    /// Which runs to email.
+    ///
+    /// Defaults to `output`.
+    pub mail_on: Option<MailOn>,
+    /// The `From` address of emails.
+    ///
+    /// Defaults to `servum`, which `sendmail` usually qualifies with the local domain.
+    pub mail_from: Option<String>,
+    /// The `sendmail`-compatible command that emails are piped into. It is run
+    /// directly, without a shell.
+    ///
+    /// Defaults to `/usr/sbin/sendmail -oi -t`.
+    pub sendmail: Option<MultiStr>,
+    /// How long (in milliseconds, or a duration such as `26h`) the task can go
+    /// without a successful run before its notifications are sent an `overdue`
+    /// outcome. This is checked even while the task is disabled, and counts from
+    /// when servum first saw the task if it has never succeeded.
+    ///
+    /// When each task last succeeded is kept in the `state-file`, so that the
+    /// window carries over restarts.
+    #[serde(deserialize_with = "optional_duration_ms")]
+    pub expect_success_within: Option<usize>,
+}
+
+/// Which runs of a task to email the output of.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MailOn {
+    /// Runs that had any output, like cron.
+    #[default]
+    Output,
+    /// Runs that failed, whether they had output or not.
+    Failure,
+    /// Every run.
+    Always,
+}
+
+impl TaskConfig {
+    /// Whether exiting with `code` counts as success, according to `success-codes`
+    /// and `failure-codes`.
+    pub fn success_code(&self, code: i32) -> bool {
+        if self.failure_codes.contains(&code) {
+            return false;
+        }
+        match &self.success_codes {
+            Some(codes) => codes.contains(&code),
+            None => code == 0 || !self.failure_codes.is_empty(),
+        }
+    }
+}
+
+/// How to retry failed runs of a task.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct Retry {
+    /// The most times to retry each start of the task.
+    ///
+    /// Defaults to `3`.
+    pub attempts: u32,
+    /// How long (in milliseconds, or a duration such as `30s`) to wait before
+    /// the first retry.
+    ///
+    /// Defaults to 30 seconds.
+    #[serde(deserialize_with = "duration_ms")]
+    pub backoff: usize,
+    /// How much longer to wait before each retry than the last.
+    ///
+    /// Defaults to `2`.
+    pub factor: u32,
+    /// The longest to wait before a retry.
+    #[serde(deserialize_with = "optional_duration_ms")]
+    pub max: Option<usize>,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: 30_000,
+            factor: 2,
+            max: None,
+        }
+    }
+}
+
+impl Retry {
+    /// How long (in milliseconds) to wait before retrying after the given attempt
+    /// failed, where the first run is attempt `1`.
+    pub fn delay(&self, attempt: u32) -> usize {
+        let delay = (self.factor as usize)
+            .saturating_pow(attempt.saturating_sub(1))
+            .saturating_mul(self.backoff);
+        self.max.map_or(delay, |max| delay.min(max))
+    }
+}
+
+/// A duration given in milliseconds, or as a string such as `10m`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DurationMs {
+    Ms(usize),
+    Str(String),
+}
+
+fn duration_ms<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    match DurationMs::deserialize(deserializer)? {
+        DurationMs::Ms(ms) => Ok(ms),
+        DurationMs::Str(s) => {
+            let duration = cli::parse_duration(&s).map_err(serde::de::Error::custom)?;
+            usize::try_from(duration.num_milliseconds())
+                .map_err(|_| serde::de::Error::custom(format!("Invalid duration `{s}`")))
+        }
+    }
+}
+
+fn optional_duration_ms<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<usize>, D::Error> {
+    duration_ms(deserializer).map(Some)
+}
+
+/// A kind of change to a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FsEvent {
+    Create,
+    Modify,
+    Remove,
+}
+
+impl FsEvent {
+    pub const ALL: [Self; 3] = [Self::Create, Self::Modify, Self::Remove];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Ready {
+    #[serde(flatten)]
+    pub check: Check,
+    /// How long (in milliseconds) to wait for the check to pass before giving
+    /// up and stopping the task.
+    ///
+    /// Defaults to 30 seconds (`30_000`).
+    #[serde(default = "Ready::default_timeout")]
+    pub timeout: usize,
+    /// How often (in milliseconds) to retry the check.
+    ///
+    /// Defaults to `250`.
+    #[serde(default = "Ready::default_interval")]
+    pub interval: usize,
+}
+
+impl Ready {
+    fn default_timeout() -> usize {
+        30_000
+    }
+
+    fn default_interval() -> usize {
+        250
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Health {
+    #[serde(flatten)]
+    pub check: Check,
+    /// How often (in milliseconds) to run the check.
+    ///
+    /// Defaults to 10 seconds (`10_000`).
+    #[serde(default = "Health::default_interval")]
+    pub interval: usize,
+    /// How long (in milliseconds) the check can take before it counts as failed.
+    ///
+    /// Defaults to 5 seconds (`5000`).
+    #[serde(default = "Health::default_timeout")]
+    pub timeout: usize,
+    /// How many checks in a row have to fail before the task is restarted.
+    ///
+    /// Defaults to `3`.
+    #[serde(default = "Health::default_failure_threshold")]
+    pub failure_threshold: u32,
+}
+
+impl Health {
+    fn default_interval() -> usize {
+        10_000
+    }
+
+    fn default_timeout() -> usize {
+        5000
+    }
+
+    fn default_failure_threshold() -> u32 {
+        3
+    }
+}
+
+/// Something that can be checked about a running task.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Check {
+    /// A TCP address (e.g. `127.0.0.1:8080`) accepts connections.
+    Tcp(String),
+    /// A file exists.
+    File(PathBuf),
+    /// A unix socket accepts connections.
+    Socket(PathBuf),
+    /// A command exits successfully. It is run the same way as `cmd`.
+    Cmd(MultiStr),
+    /// A `GET` request to an `http://` URL (e.g. `http://localhost:8080/health`)
+    /// gets a 2xx or 3xx response. `https://` URLs aren't supported.
+    Http(String),
+    /// A file has been modified recently.
+    Heartbeat(Heartbeat),
+    /// A line of stdout matches a regex. Only supported by `ready`.
+    Stdout(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Heartbeat {
+    pub file: PathBuf,
+    /// How long ago (in milliseconds) the file can have last been modified.
+    pub max_age: usize,
+}
+
+/// A notification about the outcome of a task.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Notify {
+    #[serde(flatten)]
+    pub action: NotifyAction,
+    /// Which outcomes to notify about.
+    ///
+    /// Defaults to `["failure", "overdue"]`.
+    #[serde(default = "Notify::default_on")]
+    pub on: Vec<Outcome>,
+    /// The shortest time (in milliseconds, or a duration such as `15m`) between
+    /// notifications from this action for a task. Any in between are dropped.
+    #[serde(default, deserialize_with = "optional_duration_ms")]
+    pub rate_limit: Option<usize>,
+    /// How many runs in a row have to fail to count as a `crash-loop`.
+    ///
+    /// Defaults to `5`.
+    #[serde(default = "Notify::default_crash_loop")]
+    pub crash_loop: u32,
+}
+
+impl Notify {
+    fn default_on() -> Vec<Outcome> {
+        vec![Outcome::Failure, Outcome::Overdue]
+    }
+
+    fn default_crash_loop() -> u32 {
+        5
+    }
+}
+
+/// How to send a notification.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotifyAction {
+    /// Run a command, which is run the same way as `cmd`. Details are passed in
+    /// `SERVUM_TASK`, `SERVUM_OUTCOME`, `SERVUM_RUN_ID`, `SERVUM_EXIT_CODE`,
+    /// `SERVUM_SIGNAL` and `SERVUM_OUTPUT_TAIL`.
+    Cmd(MultiStr),
+    /// `POST` the details as JSON to an `http://` URL.
+    ///
+    /// `https://` URLs aren't supported, so for webhooks that need TLS, use a
+    /// `cmd` such as `curl` instead.
+    Url(String),
+}
+
+impl NotifyAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Cmd(_) => "cmd",
+            Self::Url(_) => "url",
+        }
+    }
+}
+
+/// An outcome of a task that can be notified about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    /// A run failed, other than by being stopped.
+    Failure,
+    /// A run succeeded after the last one failed.
+    Recovery,
+    /// A run's `ready` check timed out, or a start was queued for longer than
+    /// the `queue-timeout`.
+    Timeout,
+    /// Enough runs failed in a row to reach `crash-loop`.
+    CrashLoop,
+    /// The task hasn't succeeded within its `expect-success-within`.
+    Overdue,
+}
+
+impl Outcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Failure => "failure",
+            Self::Recovery => "recovery",
+            Self::Timeout => "timeout",
+            Self::CrashLoop => "crash-loop",
+            Self::Overdue => "overdue",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RequirementFailure {
+    /// Don't start this task until the requirement has recovered.
+    #[default]
+    Block,
+    /// Also stop this task if it is running.
+    Stop,
 }
 
 impl Default for TaskConfig {
@@ -82,6 +565,34 @@ impl Default for TaskConfig {
             stop_timeout: 10_000,
             on_start: false,
             enabled: true,
+            requires: vec![],
+            after: vec![],
+            requirement_failure: RequirementFailure::Block,
+            ready: None,
+            health: None,
+            on_success: vec![],
+            on_failure: vec![],
+            on_complete: vec![],
+            watch_paths: vec![],
+            watch_events: FsEvent::ALL.to_vec(),
+            watch_debounce: 500,
+            restart_on_change: vec![],
+            limits: vec![],
+            priority: 0,
+            queue_timeout: None,
+            lock: None,
+            lock_file: None,
+            retry: None,
+            success_codes: None,
+            failure_codes: vec![],
+            fail_on_output: None,
+            succeed_on_output: None,
+            notify: vec![],
+            mail_to: None,
+            mail_on: None,
+            mail_from: None,
+            sendmail: None,
+            expect_success_within: None,
         }
     }
 }
@@ -103,6 +614,9 @@ pub struct Task {
     /// A custom env vars for this task.
     /// Can be set to `false` to unset (only applies when extending a task).
     pub env: Overridable<Inheritable<Env>>,
+    /// Where the output of this task should go.
+    /// Can be set to `false` to unset (the global `log` config will not be used either).
+    pub log: Overridable<Inheritable<Log>>,
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
@@ -138,6 +652,9 @@ pub struct Watch {
     pub enabled: bool,
     /// Whether to force the usage of the fallback poll-watcher. Mostly as an
     /// escape hatch if the default doesn't work for some reason.
+    ///
+    /// This applies to the `watch-paths` and `restart-on-change` of tasks too,
+    /// and is only read on startup.
     pub force_poll: bool,
 }
 
@@ -150,6 +667,225 @@ impl Default for Watch {
     }
 }
 
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct Log {
+    /// Where the stdout of the task should be sent.
+    ///
+    /// Defaults to `inherit`.
+    pub stdout: Option<LogTarget>,
+    /// Where the stderr of the task should be sent.
+    /// Ignored if `merge-stderr` is enabled.
+    ///
+    /// Defaults to `inherit`.
+    pub stderr: Option<LogTarget>,
+    /// If enabled, then stderr will be written to the same place as stdout.
+    ///
+    /// Defaults to `false`.
+    pub merge_stderr: Option<bool>,
+    /// How log files should be rotated. Only applies to `file` targets.
+    ///
+    /// If not set, then log files will grow forever.
+    pub rotate: Option<Rotate>,
+    /// How many of the most recent lines of output to keep in memory.
+    /// These can be read using `servum logs`, regardless of the targets above.
+    ///
+    /// Defaults to 1000.
+    pub buffer: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogTarget {
+    /// Write to the same stream as this process.
+    Inherit,
+    /// Throw the output away.
+    Discard,
+    /// Append to the given file.
+    ///
+    /// The path can contain the following placeholders:
+    ///
+    /// - `{task}`: the id of the task
+    /// - `{date}`: the current date, formatted as `YYYY-MM-DD`
+    File(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct Rotate {
+    /// Rotate the file once it grows beyond this many bytes.
+    pub size: Option<u64>,
+    /// Rotate the file at the start of every period.
+    pub every: Option<RotatePeriod>,
+    /// How many rotated files to keep around.
+    ///
+    /// Defaults to 5.
+    pub keep: usize,
+}
+
+impl Default for Rotate {
+    fn default() -> Self {
+        Self {
+            size: None,
+            every: None,
+            keep: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RotatePeriod {
+    Hourly,
+    Daily,
+    Weekly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct Control {
+    /// The path of the unix socket used to talk to a running instance.
+    ///
+    /// Defaults to the path of the config file with a `.sock` extension.
+    pub socket: Option<PathBuf>,
+    /// The file permissions to give the socket.
+    ///
+    /// Defaults to `0o600`.
+    pub mode: u32,
+    /// Users (other than root and the user servum is running as) that are
+    /// allowed to send commands.
+    ///
+    /// This is checked using the credentials of the connecting process, so
+    /// it applies even if `mode` allows others to connect.
+    pub allow_uids: Vec<u32>,
+    /// Groups whose users are allowed to send commands.
+    ///
+    /// On Linux, both the primary and supplementary groups of the connecting
+    /// process are checked. Elsewhere, only its primary group is.
+    pub allow_gids: Vec<u32>,
+}
+
+impl Default for Control {
+    fn default() -> Self {
+        Self {
+            socket: None,
+            mode: 0o600,
+            allow_uids: vec![],
+            allow_gids: vec![],
+        }
+    }
+}
+
+/// Config for the record of every finished run, which `servum history` reads.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct History {
+    /// The file runs are recorded in, one JSON object per line.
+    ///
+    /// Defaults to the path of the config file with a `.history` extension.
+    pub file: Option<PathBuf>,
+    /// The most runs to keep, with the oldest dropped first. If set to 0, then
+    /// runs aren't recorded at all.
+    ///
+    /// Defaults to `10000`.
+    pub keep: usize,
+    /// How long (in milliseconds, or a duration such as `30d`) to keep runs for.
+    ///
+    /// If not set, then runs are only dropped once there are more than `keep`.
+    #[serde(deserialize_with = "optional_duration_ms")]
+    pub max_age: Option<usize>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            file: None,
+            keep: 10_000,
+            max_age: None,
+        }
+    }
+}
+
+/// Config for the log of scheduler activity.
+///
+/// Every event has an `event` field naming it, along with a `message` meant for
+/// humans. In `json` mode, each event is written as a single line to stderr, and
+/// the following field names are stable. Any `error` is the error followed by
+/// its causes, on one line.
+///
+/// - `task.scheduled`: `task`, `next` (RFC 3339)
+/// - `task.started`: `task`, `run`, `trigger`, `pid`, `argv` (a JSON array encoded as a
+///   string, so it has to be decoded separately), `triggered_by` and `triggered_by_run`
+///   (for `on-success`, `on-failure` and `on-complete`), `attempt` (`1` unless `trigger`
+///   is `retry`)
+/// - `task.start_failed`: `task`, `trigger`, `error`
+/// - `task.finished`: `task`, `run`, `pid`, `success` (according to `success-codes`,
+///   `failure-codes`, `fail-on-output` and `succeed-on-output`), `exit_code` (if exited),
+///   `signal` (if killed by a signal), `duration_ms`, `error` (if it couldn't be waited on)
+/// - `task.skipped`: `task`, `trigger`, `reason` (`overlap`, `requirement`, `disabled`,
+///   `queued`, `queue-timeout` or `locked`), `running_run` (for `overlap`), `requirement`
+///   (for `requirement`), `waited_ms` (for `queue-timeout`), `lock_file` (for `locked`)
+/// - `task.queued`: `task`, `trigger`, `limit` (`max-concurrent`, or the name of the full
+///   limit or held lock), `priority`
+/// - `task.retrying`: `task`, `run` (the failed run), `attempt` (of the retry), `delay_ms`
+/// - `task.retry_cancelled`: `task`, `attempt`, `trigger` (of the start replacing it)
+/// - `task.stop_requested`: `task`, `run`, `pid`, `method` (`cmd-stop`, `signal` or `kill`),
+///   `timeout_ms`
+/// - `task.stop_cmd_failed`: `task`, `run`, `error`
+/// - `task.output_timeout`: `task`, `run`, `waited_ms` (after exiting, for the output
+///   to close)
+/// - `task.killed`: `task`, `run`, `pid`, `reason` (`timeout` or `cmd-stop-failed`)
+/// - `task.requirement_failed`: `task`, `requirement`, `action` (`stop`)
+/// - `task.waiting`: `task`, `trigger`, `requirement`
+/// - `task.ready`: `task`, `run`, `duration_ms`
+/// - `task.ready_failed`: `task`, `run`, `reason` (`timeout` or `error`), `error`
+///   (for `error`)
+/// - `task.health_failed_to_start`: `task`, `run`, `error`
+/// - `task.health_failed`: `task`, `run`, `failures` (in a row), `threshold`
+/// - `task.unhealthy`: `task`, `run`, `failures`, `action` (`restart`)
+/// - `task.watch_failed`: `task`, `error`
+/// - `task.output_read_failed`: `task`, `error`
+/// - `task.output_write_failed`: `task`, `error` (such as failing to open or rotate its log)
+/// - `task.sources_changed`: `task`, `run` (if running), `paths` (newline-separated)
+/// - `task.overdue`: `task`, `last_success` (RFC 3339, if it has ever succeeded),
+///   `window_ms`
+/// - `notify.sent`: `task`, `outcome` (`failure`, `recovery`, `timeout`, `crash-loop` or
+///   `overdue`),
+///   `action` (`cmd` or `url`)
+/// - `notify.suppressed`: `task`, `outcome`
+/// - `notify.failed`: `task`, `outcome`, `error`
+/// - `mail.sent`: `task`, `run`, `to`
+/// - `mail.failed`: `task`, `run`, `error`
+/// - `state.save_failed`: `path`, `error`
+/// - `history.failed`: `task`, `run`, `error`
+/// - `control.rejected`: `uid`, `gid` (the primary group of the connecting process)
+/// - `control.failed`: `error`
+/// - `control.accept_failed`: `error`
+/// - `config.reloaded`: `added`, `removed`, `changed` (comma-separated task ids)
+/// - `config.reload_failed`: `error`
+/// - `shutdown.strict`: `task` (the failed `on-start` task, with `--strict`)
+/// - `shutdown`: no extra fields
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct Events {
+    /// How events should be formatted. Can be overridden with `--event-format`.
+    ///
+    /// This is only read on startup.
+    ///
+    /// Defaults to `text`.
+    pub format: EventFormat,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventFormat {
+    /// Human readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
 /// A simple wrapper to allow either `"single string"` or `["multiple", "strings"]`
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -219,19 +955,93 @@ where
 
 type Rstr = Rc<String>;
 
+/// A task with everything it extends applied.
+///
+/// Strings that can be inherited are stored as `S`, which allows the resolver
+/// to record extra information about each one (see [`Config::resolve_tasks`]).
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
-pub struct ResolvedTask {
+pub struct ResolvedTask<S = Rstr>
+where
+    S: Hash + Eq,
+{
     pub config: TaskConfig,
     // TODO: validate that first value resolves to a valid file.
-    pub shell: Option<Vec<Rstr>>,
-    pub path: Option<Path<Rstr>>,
-    pub env: Option<Env<Rstr>>,
+    pub shell: Option<Vec<S>>,
+    pub path: Option<Path<S>>,
+    pub env: Option<Env<S>>,
+    pub log: Option<Log>,
+}
+
+/// Which part of a task an inheritable string came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field<'a> {
+    Shell,
+    PathDir,
+    EnvKey,
+    EnvValue { key: &'a str },
 }
 
 impl TryFrom<Config> for (Watch, HashMap<String, ResolvedTask>) {
     type Error = eyre::Error;
 
-    fn try_from(Config { mut tasks, watch }: Config) -> Result<Self, Self::Error> {
+    fn try_from(config: Config) -> Result<Self, Self::Error> {
+        let watch = config.watch.clone();
+        let tasks = config.resolve_tasks(|_, _, value| Rc::new(value))?;
+        Ok((watch, tasks))
+    }
+}
+
+impl Config {
+    /// The path of the state file, given the path of the config file.
+    pub fn state_path(&self, config: &std::path::Path) -> PathBuf {
+        self.state_file
+            .clone()
+            .unwrap_or_else(|| config.with_extension("state"))
+    }
+
+    pub fn concurrency(&self) -> Concurrency {
+        Concurrency {
+            max: self.max_concurrent,
+            limits: self.limits.clone(),
+        }
+    }
+
+    /// Resolves every task, applying everything that it extends.
+    ///
+    /// Each inheritable string is passed through `tag` along with the id of the
+    /// task that set it, so that it can be tracked through the resolution.
+    pub fn resolve_tasks<S>(
+        self,
+        tag: impl Fn(&str, Field, String) -> S,
+    ) -> eyre::Result<HashMap<String, ResolvedTask<S>>>
+    where
+        S: Hash + Eq + Clone,
+    {
+        let Config {
+            mut tasks,
+            watch: _,
+            log,
+            control: _,
+            events: _,
+            history: _,
+            max_concurrent: _,
+            limits,
+            notify,
+            mail_to,
+            mail_on,
+            mail_from,
+            sendmail,
+            state_file: _,
+        } = self;
+
+        for (id, task) in &tasks {
+            for limit in &task.config.limits {
+                if !limits.contains_key(limit) {
+                    eyre::bail!("Unknown limit `{limit}` in task `{id}`");
+                }
+            }
+        }
+
         // Check that all tasks extend from known tasks.
         for task in tasks.values() {
             let Some(extends) = &task.extends else {
@@ -256,7 +1066,10 @@ impl TryFrom<Config> for (Watch, HashMap<String, ResolvedTask>) {
 
         let mut resolved: HashMap<_, _> = tasks
             .extract_if(|_k, v| v.extends.is_empty())
-            .map(|(k, task)| (k, task.into()))
+            .map(|(k, task)| {
+                let task = ResolvedTask::root(task, log.as_ref(), |f, v| tag(&k, f, v));
+                (k, task)
+            })
             .collect();
 
         while !tasks.is_empty() {
@@ -264,7 +1077,7 @@ impl TryFrom<Config> for (Watch, HashMap<String, ResolvedTask>) {
             let mut next = HashMap::new();
 
             for (id, task) in tasks {
-                match resolve_task(task, &resolved) {
+                match resolve_task(task, &resolved, |f, v| tag(&id, f, v)) {
                     Ok(task) => {
                         resolved.insert(id, task);
                     }
@@ -281,15 +1094,129 @@ impl TryFrom<Config> for (Watch, HashMap<String, ResolvedTask>) {
             tasks = next;
         }
 
-        Ok((watch, resolved))
+        for task in resolved.values_mut() {
+            let config = &mut task.config;
+            config.notify.extend(notify.iter().cloned());
+            config.mail_to = config.mail_to.take().or_else(|| mail_to.clone());
+            config.mail_on = config.mail_on.or(mail_on);
+            config.mail_from = config.mail_from.take().or_else(|| mail_from.clone());
+            config.sendmail = config.sendmail.take().or_else(|| sendmail.clone());
+        }
+
+        // Make sure that the tasks can actually be started in some order.
+        dependency_order(&resolved)?;
+        check_triggers(&resolved)?;
+
+        Ok(resolved)
+    }
+}
+
+impl<S> ResolvedTask<S>
+where
+    S: Hash + Eq,
+{
+    /// The tasks that this task must be started after.
+    pub fn dependencies(&self) -> impl Iterator<Item = &String> {
+        self.config.requires.iter().chain(&self.config.after)
+    }
+
+    /// Every task that this task can start when it exits.
+    pub fn triggers(&self) -> impl Iterator<Item = &String> {
+        self.config
+            .on_success
+            .iter()
+            .chain(&self.config.on_failure)
+            .chain(&self.config.on_complete)
+    }
+}
+
+/// Orders tasks so that every task comes after its `requires` and `after` tasks.
+///
+/// Tasks that could go in either order are sorted by id.
+pub fn dependency_order<S>(tasks: &HashMap<String, ResolvedTask<S>>) -> eyre::Result<Vec<String>>
+where
+    S: Hash + Eq,
+{
+    for (id, task) in tasks {
+        for dep in task.dependencies() {
+            if !tasks.contains_key(dep) {
+                eyre::bail!("Unknown task `{dep}` in the dependencies of `{id}`");
+            }
+        }
+    }
+
+    let mut remaining: Vec<_> = tasks.keys().collect();
+    remaining.sort();
+    let mut order: Vec<String> = Vec::with_capacity(tasks.len());
+
+    while !remaining.is_empty() {
+        let (ready, blocked): (Vec<_>, Vec<_>) = remaining.into_iter().partition(|id| {
+            tasks[*id]
+                .dependencies()
+                .all(|dep| order.iter().any(|o| o == dep))
+        });
+
+        if ready.is_empty() {
+            let blocked: Vec<_> = blocked.iter().map(|id| format!("`{id}`")).collect();
+            eyre::bail!("Dependency cycle detected between {}", blocked.join(", "));
+        }
+
+        order.extend(ready.into_iter().cloned());
+        remaining = blocked;
+    }
+
+    Ok(order)
+}
+
+/// Checks that every task started by `on-success`, `on-failure` and
+/// `on-complete` exists, and that no task can end up triggering itself.
+fn check_triggers<S>(tasks: &HashMap<String, ResolvedTask<S>>) -> eyre::Result<()>
+where
+    S: Hash + Eq,
+{
+    for (id, task) in tasks {
+        for target in task.triggers() {
+            if !tasks.contains_key(target) {
+                eyre::bail!("Unknown task `{target}` in the triggers of `{id}`");
+            }
+        }
+    }
+
+    // Repeatedly remove tasks that either don't trigger or aren't triggered by
+    // anything that is left, which only leaves the tasks that are in a cycle.
+    let mut remaining: Vec<_> = tasks.keys().collect();
+    loop {
+        let start_len = remaining.len();
+        remaining = remaining
+            .iter()
+            .filter(|id| {
+                tasks[**id].triggers().any(|t| remaining.contains(&t))
+                    && remaining
+                        .iter()
+                        .any(|other| tasks[*other].triggers().any(|t| t == **id))
+            })
+            .copied()
+            .collect();
+        if remaining.is_empty() {
+            return Ok(());
+        }
+        if remaining.len() == start_len {
+            remaining.sort();
+            let cycle: Vec<_> = remaining.iter().map(|id| format!("`{id}`")).collect();
+            eyre::bail!("Trigger cycle detected between {}", cycle.join(", "));
+        }
     }
 }
 
 #[allow(clippy::result_large_err)]
-fn resolve_task(
+fn resolve_task<S>(
     task: Task,
-    resolved: &HashMap<String, ResolvedTask>,
-) -> Result<ResolvedTask, Task> {
+    resolved: &HashMap<String, ResolvedTask<S>>,
+    mut tag: impl FnMut(Field, String) -> S,
+) -> Result<ResolvedTask<S>, Task>
+where
+    S: Hash + Eq + Clone,
+{
     let mut parents = vec![];
 
     match &task.extends {
@@ -308,58 +1235,90 @@ fn resolve_task(
         _ => (),
     }
 
+    let retry = task
+        .config
+        .retry
+        .clone()
+        .or_else(|| parents.iter().rev().find_map(|p| p.config.retry.clone()));
+
     #[allow(clippy::type_complexity)]
-    let (shell, path, env): (Option<Vec<Rstr>>, Option<Path<Rstr>>, Option<Env<Rstr>>) = parents
+    let (shell, path, env, log): (
+        Option<Vec<S>>,
+        Option<Path<S>>,
+        Option<Env<S>>,
+        Option<Log>,
+    ) = parents
         .into_iter()
-        .fold((None, None, None), |(shell, path, env), p| {
+        .fold((None, None, None, None), |(shell, path, env, log), p| {
             (
                 match (shell, &p.shell) {
                     (Some(shell), None) => Some(shell),
                     (_, Some(shell)) => Some(shell.clone()),
                     _ => None,
                 },
-                match (path, &p.path) {
-                    (Some(path), Some(p_path)) => Some(path.merge(p_path.clone())),
-                    (Some(path), None) => Some(path),
-                    (None, Some(path)) => Some(path.clone()),
-                    _ => None,
-                },
-                match (env, &p.env) {
-                    (Some(env), Some(p_env)) => Some(env.merge(p_env.clone())),
-                    (Some(env), None) => Some(env),
-                    (None, Some(env)) => Some(env.clone()),
-                    _ => None,
-                },
+                merge_parent(path, p.path.as_ref()),
+                merge_parent(env, p.env.as_ref()),
+                merge_parent(log, p.log.as_ref()),
             )
         });
 
     Ok(ResolvedTask {
-        config: task.config,
-        shell: task.shell.map_custom(Into::into).resolve(shell.as_ref()),
+        config: TaskConfig {
+            retry,
+            ..task.config
+        },
+        shell: task
+            .shell
+            .map_custom(|s| s.tag(&mut tag))
+            .resolve(shell.as_ref()),
         path: task
             .path
-            .map_custom(|p| p.map(Into::into).resolve(path.as_ref()))
+            .map_custom(|p| p.map(|p| p.tag(&mut tag)).resolve(path.as_ref()))
             .resolve(path.as_ref()),
         env: task
             .env
-            .map_custom(|e| e.map(Into::into).resolve(env.as_ref()))
+            .map_custom(|e| e.map(|e| e.tag(&mut tag)).resolve(env.as_ref()))
             .resolve(env.as_ref()),
+        log: task
+            .log
+            .map_custom(|l| l.resolve(log.as_ref()))
+            .resolve(log.as_ref()),
     })
 }
 
-impl From<Task> for ResolvedTask {
-    fn from(task: Task) -> Self {
+fn merge_parent<T>(acc: Option<T>, parent: Option<&T>) -> Option<T>
+where
+    T: Mergeable + Clone,
+{
+    match (acc, parent) {
+        (Some(acc), Some(parent)) => Some(acc.merge(parent.clone())),
+        (Some(acc), None) => Some(acc),
+        (None, Some(parent)) => Some(parent.clone()),
+        _ => None,
+    }
+}
+
+impl<S> ResolvedTask<S>
+where
+    S: Hash + Eq + Clone,
+{
+    /// Resolves a task that does not extend any others.
+    ///
+    /// The global config is used as the parent for any settings that are
+    /// applied to every task.
+    fn root(task: Task, log: Option<&Log>, mut tag: impl FnMut(Field, String) -> S) -> Self {
         ResolvedTask {
             config: task.config,
-            shell: task.shell.map_custom(Into::into).resolve(None),
+            shell: task.shell.map_custom(|s| s.tag(&mut tag)).resolve(None),
             path: task
                 .path
-                .map_custom(|p| p.map(Into::into).resolve(None))
+                .map_custom(|p| p.map(|p| p.tag(&mut tag)).resolve(None))
                 .resolve(None),
             env: task
                 .env
-                .map_custom(|e| e.map(Into::into).resolve(None))
+                .map_custom(|e| e.map(|e| e.tag(&mut tag)).resolve(None))
                 .resolve(None),
+            log: task.log.map_custom(|l| l.resolve(log)).resolve(log),
         }
     }
 }
@@ -378,11 +1337,11 @@ impl IsEmpty for Option<MultiStr> {
     }
 }
 
-impl From<MultiStr> for Vec<Rstr> {
-    fn from(value: MultiStr) -> Self {
-        match value {
-            MultiStr::Single(v) => vec![Rc::new(v)],
-            MultiStr::Multi(vs) => vs.into_iter().map(Rc::new).collect(),
+impl MultiStr {
+    fn tag<S>(self, mut tag: impl FnMut(Field, String) -> S) -> Vec<S> {
+        match self {
+            MultiStr::Single(v) => vec![tag(Field::Shell, v)],
+            MultiStr::Multi(vs) => vs.into_iter().map(|v| tag(Field::Shell, v)).collect(),
         }
     }
 }
@@ -391,16 +1350,23 @@ pub trait Mergeable {
     fn merge(self, other: Self) -> Self;
 }
 
-impl From<Path<String>> for Path<Rstr> {
-    fn from(value: Path<String>) -> Self {
-        Self {
-            dirs: value.dirs.into_iter().map(Rc::new).collect(),
-            apply: value.apply,
+impl Path {
+    fn tag<S>(self, mut tag: impl FnMut(Field, String) -> S) -> Path<S> {
+        Path {
+            dirs: self
+                .dirs
+                .into_iter()
+                .map(|d| tag(Field::PathDir, d))
+                .collect(),
+            apply: self.apply,
         }
     }
 }
 
-impl Mergeable for Path<Rstr> {
+impl<S> Mergeable for Path<S>
+where
+    S: PartialEq,
+{
     fn merge(mut self, mut other: Self) -> Self {
         other.dirs.append(&mut self.dirs);
         other.dirs.dedup();
@@ -411,20 +1377,29 @@ impl Mergeable for Path<Rstr> {
     }
 }
 
-impl From<Env<String>> for Env<Rstr> {
-    fn from(value: Env<String>) -> Self {
-        Self {
-            vars: value
+impl Env {
+    fn tag<S>(self, mut tag: impl FnMut(Field, String) -> S) -> Env<S>
+    where
+        S: Hash + Eq,
+    {
+        Env {
+            vars: self
                 .vars
                 .into_iter()
-                .map(|(k, v)| (Rc::new(k), Rc::new(v)))
+                .map(|(k, v)| {
+                    let value = tag(Field::EnvValue { key: &k }, v);
+                    (tag(Field::EnvKey, k), value)
+                })
                 .collect(),
-            merge: value.merge,
+            merge: self.merge,
         }
     }
 }
 
-impl Mergeable for Env<Rstr> {
+impl<S> Mergeable for Env<S>
+where
+    S: Hash + Eq,
+{
     fn merge(mut self, other: Self) -> Self {
         self.vars.extend(other.vars);
         Self {
@@ -434,13 +1409,25 @@ impl Mergeable for Env<Rstr> {
     }
 }
 
+impl Mergeable for Log {
+    fn merge(self, other: Self) -> Self {
+        Self {
+            stdout: other.stdout.or(self.stdout),
+            stderr: other.stderr.or(self.stderr),
+            merge_stderr: other.merge_stderr.or(self.merge_stderr),
+            rotate: other.rotate.or(self.rotate),
+            buffer: other.buffer.or(self.buffer),
+        }
+    }
+}
+
 impl<T> Overridable<T>
 where
     T: Clone,
 {
     pub fn resolve(self, parent: Option<&T>) -> Option<T> {
         match (self, parent) {
-            (Self::Use(true), Some(parent)) | (Self::Unset, Some(parent)) => Some(parent.clone()),
+            (Self::Use(true) | Self::Unset, Some(parent)) => Some(parent.clone()),
             (Self::Custom(v), _) => Some(v),
             _ => None,
         }
@@ -32,6 +32,26 @@ fn test_parse_watch() {
             enabled: false,
             force_poll: true,
         },
+        ..Default::default()
+    };
+
+    assert_eq!(parsed, config);
+}
+
+#[test]
+fn test_parse_events() {
+    let parsed: Config = "
+        [events]
+        format = 'json'
+    "
+    .parse()
+    .unwrap();
+
+    let config = Config {
+        events: Events {
+            format: EventFormat::Json,
+        },
+        ..Default::default()
     };
 
     assert_eq!(parsed, config);
@@ -49,7 +69,7 @@ fn test_parse_empty_task() {
         tasks: hash_map! {
             "foo".to_owned() => Task::default(),
         },
-        watch: Watch::default(),
+        ..Default::default()
     };
 
     assert_eq!(parsed, config);
@@ -84,7 +104,7 @@ fn test_parse_multi_empty_tasks() {
                 ..Default::default()
             },
         },
-        watch: Watch::default(),
+        ..Default::default()
     };
 
     assert_eq!(parsed, config);
@@ -170,7 +190,7 @@ fn test_parse_complex_tasks() {
                 ..Default::default()
             }
         },
-        watch: Watch::default(),
+        ..Default::default()
     };
 
     assert_eq!(parsed, config);
@@ -188,7 +208,7 @@ fn test_resolve_empty() {
 
     let tasks = hash_map!();
 
-    assert_eq!(resolved, tasks)
+    assert_eq!(resolved, tasks);
 }
 
 #[test]
@@ -230,7 +250,7 @@ fn test_resolve_simple() {
         },
     };
 
-    assert_eq!(resolved, tasks)
+    assert_eq!(resolved, tasks);
 }
 
 #[test]
@@ -335,7 +355,126 @@ fn test_resolve_complex() {
         },
     };
 
-    assert_eq!(resolved, tasks)
+    assert_eq!(resolved, tasks);
+}
+
+#[test]
+fn test_parse_log() {
+    let parsed: Config = "
+        [log]
+        stdout = { file = '/var/log/servum/{task}.log' }
+        merge-stderr = true
+        rotate = { size = 1024, keep = 3 }
+
+        [task.foo.log]
+        stdout = 'discard'
+        stderr = 'inherit'
+        rotate = { every = 'daily' }
+
+        [task.bar]
+        log = false
+    "
+    .parse()
+    .unwrap();
+
+    let config = Config {
+        tasks: hash_map! {
+            "foo".to_owned() => Task {
+                log: Overridable::Custom(Inheritable {
+                    config: Log {
+                        stdout: Some(LogTarget::Discard),
+                        stderr: Some(LogTarget::Inherit),
+                        rotate: Some(Rotate {
+                            every: Some(RotatePeriod::Daily),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            "bar".to_owned() => Task {
+                log: Overridable::Use(false),
+                ..Default::default()
+            },
+        },
+        log: Some(Log {
+            stdout: Some(LogTarget::File("/var/log/servum/{task}.log".to_owned())),
+            merge_stderr: Some(true),
+            rotate: Some(Rotate {
+                size: Some(1024),
+                keep: 3,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    assert_eq!(parsed, config);
+}
+
+#[test]
+fn test_resolve_log() {
+    let (_, resolved) = "
+        [log]
+        stdout = { file = 'out.log' }
+        merge-stderr = true
+
+        [task.foo.log]
+        stderr = 'discard'
+        merge-stderr = false
+
+        [task.bar]
+        extends = 'foo'
+
+        [task.bar.log]
+        stdout = 'inherit'
+
+        [task.baz]
+        extends = 'foo'
+        log = false
+
+        [task.qoz.log]
+        replace = true
+        stdout = 'discard'
+    "
+    .parse::<Config>()
+    .unwrap()
+    .try_into()
+    .unwrap();
+
+    let tasks = hash_map! {
+        "foo".to_owned() => ResolvedTask {
+            log: Some(Log {
+                stdout: Some(LogTarget::File("out.log".to_owned())),
+                stderr: Some(LogTarget::Discard),
+                merge_stderr: Some(false),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        "bar".to_owned() => ResolvedTask {
+            log: Some(Log {
+                stdout: Some(LogTarget::Inherit),
+                stderr: Some(LogTarget::Discard),
+                merge_stderr: Some(false),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        "baz".to_owned() => ResolvedTask::default(),
+        "qoz".to_owned() => ResolvedTask {
+            log: Some(Log {
+                stdout: Some(LogTarget::Discard),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    };
+
+    assert_eq!(resolved, tasks);
 }
 
 #[test]
@@ -364,7 +503,384 @@ fn test_path_merge() {
             dirs,
             apply: PathApplyMethod::After,
         }
-    )
+    );
+}
+
+#[test]
+fn test_dependency_order() {
+    let (_, resolved): (_, HashMap<String, ResolvedTask>) = "
+        [task.app]
+        requires = ['proxy']
+        after = ['migrate']
+
+        [task.proxy]
+
+        [task.migrate]
+        requires = ['proxy']
+
+        [task.other]
+    "
+    .parse::<Config>()
+    .unwrap()
+    .try_into()
+    .unwrap();
+
+    assert_eq!(
+        dependency_order(&resolved).unwrap(),
+        ["other", "proxy", "migrate", "app"]
+    );
+}
+
+#[test]
+fn test_dependency_errors() {
+    let resolve = |config: &str| {
+        config
+            .parse::<Config>()
+            .unwrap()
+            .resolve_tasks(|_, _, v| Rc::new(v))
+            .unwrap_err()
+            .to_string()
+    };
+
+    assert_eq!(
+        resolve("[task.app]\nrequires = ['nope']"),
+        "Unknown task `nope` in the dependencies of `app`"
+    );
+    assert_eq!(
+        resolve("[task.a]\nafter = ['b']\n[task.b]\nrequires = ['a']\n[task.c]"),
+        "Dependency cycle detected between `a`, `b`"
+    );
+}
+
+#[test]
+fn test_parse_ready() {
+    let parsed: Config = "
+        [task.db]
+        ready.tcp = '127.0.0.1:5432'
+        ready.timeout = 5000
+
+        [task.app]
+        ready = { stdout = 'listening on \\d+' }
+    "
+    .parse()
+    .unwrap();
+
+    assert_eq!(
+        parsed.tasks["db"].config.ready,
+        Some(Ready {
+            check: Check::Tcp("127.0.0.1:5432".to_owned()),
+            timeout: 5000,
+            interval: 250,
+        })
+    );
+    assert_eq!(
+        parsed.tasks["app"].config.ready,
+        Some(Ready {
+            check: Check::Stdout("listening on \\d+".to_owned()),
+            timeout: 30_000,
+            interval: 250,
+        })
+    );
+}
+
+#[test]
+fn test_trigger_errors() {
+    let resolve = |config: &str| {
+        config
+            .parse::<Config>()
+            .unwrap()
+            .resolve_tasks(|_, _, v| Rc::new(v))
+            .map_err(|e| e.to_string())
+            .map(|_| ())
+    };
+
+    assert_eq!(
+        resolve("[task.backup]\non-success = ['nope']"),
+        Err("Unknown task `nope` in the triggers of `backup`".to_owned())
+    );
+    assert_eq!(
+        resolve(
+            "
+            [task.backup]
+            on-success = ['verify']
+            [task.verify]
+            on-failure = ['backup']
+            on-complete = ['prune']
+            [task.prune]
+            [task.start]
+            on-complete = ['backup']
+            "
+        ),
+        Err("Trigger cycle detected between `backup`, `verify`".to_owned())
+    );
+    assert_eq!(
+        resolve("[task.a]\non-complete = ['b', 'c']\n[task.b]\non-success = ['c']\n[task.c]"),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_parse_health() {
+    let parsed: Config = "
+        [task.web.health]
+        http = 'http://localhost:8080/health'
+        interval = 1000
+
+        [task.worker]
+        health.heartbeat = { file = '/run/worker', max-age = 60000 }
+        health.failure-threshold = 1
+    "
+    .parse()
+    .unwrap();
+
+    assert_eq!(
+        parsed.tasks["web"].config.health,
+        Some(Health {
+            check: Check::Http("http://localhost:8080/health".to_owned()),
+            interval: 1000,
+            timeout: 5000,
+            failure_threshold: 3,
+        })
+    );
+    assert_eq!(
+        parsed.tasks["worker"].config.health,
+        Some(Health {
+            check: Check::Heartbeat(Heartbeat {
+                file: "/run/worker".into(),
+                max_age: 60000,
+            }),
+            interval: 10_000,
+            timeout: 5000,
+            failure_threshold: 1,
+        })
+    );
+}
+
+#[test]
+fn test_parse_limits() {
+    let parsed: Config = "
+        max-concurrent = 4
+
+        [limit.db]
+        max = 2
+
+        [task.backup]
+        limits = ['db']
+        priority = 10
+        queue-timeout = 60000
+    "
+    .parse()
+    .unwrap();
+
+    assert_eq!(
+        parsed.concurrency(),
+        Concurrency {
+            max: Some(4),
+            limits: HashMap::from([("db".to_owned(), Limit { max: 2 })]),
+        }
+    );
+    let backup = &parsed.tasks["backup"].config;
+    assert_eq!(backup.limits, ["db"]);
+    assert_eq!(backup.priority, 10);
+    assert_eq!(backup.queue_timeout, Some(60000));
+
+    assert_eq!(
+        "[task.a]\nlimits = ['nope']"
+            .parse::<Config>()
+            .unwrap()
+            .resolve_tasks(|_, _, v| Rc::new(v))
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        Err("Unknown limit `nope` in task `a`".to_owned())
+    );
+}
+
+#[test]
+fn test_parse_retry() {
+    let tasks: HashMap<String, ResolvedTask> = "
+        [task.base]
+        retry = { attempts = 5, backoff = '30s', max = '2m' }
+
+        [task.sync]
+        extends = 'base'
+
+        [task.once]
+        extends = 'base'
+        retry.attempts = 1
+    "
+    .parse::<Config>()
+    .unwrap()
+    .resolve_tasks(|_, _, v| Rc::new(v))
+    .unwrap();
+
+    let retry = Retry {
+        attempts: 5,
+        backoff: 30_000,
+        factor: 2,
+        max: Some(120_000),
+    };
+    assert_eq!(tasks["sync"].config.retry.as_ref(), Some(&retry));
+    assert_eq!(
+        tasks["once"].config.retry,
+        Some(Retry {
+            attempts: 1,
+            ..Retry::default()
+        })
+    );
+    assert_eq!(
+        (1..=4)
+            .map(|attempt| retry.delay(attempt))
+            .collect::<Vec<_>>(),
+        [30_000, 60_000, 120_000, 120_000]
+    );
+    assert!("[task.a]\nretry.backoff = '30'".parse::<Config>().is_err());
+}
+
+#[test]
+fn test_success_codes() {
+    let task = |config: &str| {
+        let mut parsed: Config = format!("[task.a]\n{config}").parse().unwrap();
+        parsed.tasks.remove("a").unwrap().config
+    };
+    let successes = |config: &TaskConfig| {
+        (0..4)
+            .filter(|code| config.success_code(*code))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(successes(&task("")), [0]);
+    assert_eq!(successes(&task("success-codes = [0, 1]")), [0, 1]);
+    assert_eq!(successes(&task("failure-codes = [2]")), [0, 1, 3]);
+    assert_eq!(
+        successes(&task("success-codes = [0, 1, 2]\nfailure-codes = [2]")),
+        [0, 1]
+    );
+}
+
+#[test]
+fn test_parse_notify() {
+    let tasks: HashMap<String, ResolvedTask> = "
+        [[notify]]
+        url = 'http://alerts.local/servum'
+        on = ['failure', 'recovery']
+        rate-limit = '15m'
+
+        [[task.backup.notify]]
+        cmd = 'page-oncall'
+        on = ['crash-loop']
+        crash-loop = 3
+
+        [task.prune]
+    "
+    .parse::<Config>()
+    .unwrap()
+    .resolve_tasks(|_, _, v| Rc::new(v))
+    .unwrap();
+
+    let global = Notify {
+        action: NotifyAction::Url("http://alerts.local/servum".to_owned()),
+        on: vec![Outcome::Failure, Outcome::Recovery],
+        rate_limit: Some(900_000),
+        crash_loop: 5,
+    };
+    assert_eq!(
+        tasks["backup"].config.notify,
+        [
+            Notify {
+                action: NotifyAction::Cmd(MultiStr::Single("page-oncall".to_owned())),
+                on: vec![Outcome::CrashLoop],
+                rate_limit: None,
+                crash_loop: 3,
+            },
+            global.clone(),
+        ]
+    );
+    assert_eq!(tasks["prune"].config.notify, [global]);
+}
+
+#[test]
+fn test_parse_mail() {
+    let tasks: HashMap<String, ResolvedTask> = "
+        mail-to = 'ops@example.com'
+        sendmail = '/usr/bin/msmtp -t'
+
+        [task.backup]
+        mail-on = 'failure'
+
+        [task.noisy]
+        mail-to = ''
+    "
+    .parse::<Config>()
+    .unwrap()
+    .resolve_tasks(|_, _, v| Rc::new(v))
+    .unwrap();
+
+    let backup = &tasks["backup"].config;
+    assert_eq!(backup.mail_to.as_deref(), Some("ops@example.com"));
+    assert_eq!(backup.mail_on, Some(MailOn::Failure));
+    assert_eq!(
+        backup.sendmail,
+        Some(MultiStr::Single("/usr/bin/msmtp -t".to_owned()))
+    );
+    assert_eq!(tasks["noisy"].config.mail_to.as_deref(), Some(""));
+    assert_eq!(tasks["noisy"].config.mail_on, None);
+}
+
+#[test]
+fn test_parse_expect_success_within() {
+    let config: Config = "
+        state-file = '/var/lib/servum/state'
+
+        [task.backup]
+        expect-success-within = '26h'
+
+        [[task.backup.notify]]
+        cmd = 'page-oncall'
+
+        [task.report]
+        expect-success-within = 60000
+    "
+    .parse()
+    .unwrap();
+
+    assert_eq!(
+        config.state_path("servum.toml".as_ref()),
+        PathBuf::from("/var/lib/servum/state")
+    );
+    assert_eq!(
+        Config::default().state_path("/etc/servum.toml".as_ref()),
+        PathBuf::from("/etc/servum.state")
+    );
+
+    let tasks = config.resolve_tasks(|_, _, v| Rc::new(v)).unwrap();
+    let backup = &tasks["backup"].config;
+    assert_eq!(backup.expect_success_within, Some(26 * 60 * 60 * 1000));
+    assert_eq!(backup.notify[0].on, [Outcome::Failure, Outcome::Overdue]);
+    assert_eq!(tasks["report"].config.expect_success_within, Some(60_000));
+}
+
+#[test]
+fn test_parse_history() {
+    let config: Config = "
+        [history]
+        keep = 500
+        max-age = '30d'
+    "
+    .parse()
+    .unwrap();
+
+    assert_eq!(
+        config.history,
+        History {
+            file: None,
+            keep: 500,
+            max_age: Some(30 * 24 * 60 * 60 * 1000),
+        }
+    );
+    assert_eq!(
+        config.history.file_path("/etc/servum.toml".as_ref()),
+        PathBuf::from("/etc/servum.history")
+    );
 }
 
 #[test]
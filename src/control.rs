@@ -0,0 +1,352 @@
+#[cfg(test)]
+mod test;
+
+use std::{
+    fs::{self, Permissions},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Sender},
+    thread,
+};
+
+use chrono::{DateTime, Local};
+use color_eyre::eyre::{self, WrapErr};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{config::Control, log::Line, scheduler::Event};
+
+/// The version of the control protocol.
+///
+/// This is bumped whenever a breaking change is made to the request or
+/// response formats.
+pub const VERSION: u32 = 1;
+
+/// A single request, sent as one line of JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Request {
+    pub version: u32,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Command {
+    /// Get the current state of every task.
+    Status,
+    /// Start a task now, as long as it isn't already running.
+    Start { task: String },
+    /// Stop a running task.
+    Stop { task: String },
+    /// Stop a task if it is running, then start it again.
+    Restart { task: String },
+    /// Enable a task until the config next changes it.
+    Enable { task: String },
+    /// Disable (and stop) a task until the config next changes it.
+    Disable { task: String },
+    /// Reload the config file.
+    Reload,
+    /// Stop every task and exit.
+    Shutdown,
+    /// Read the buffered output of a task.
+    Logs {
+        task: String,
+        /// Only include output from this run.
+        run: Option<u64>,
+        /// Keep sending new output as it arrives.
+        follow: bool,
+    },
+    /// Start a task now and stream its output until it finishes.
+    RunOnce { task: String },
+}
+
+/// A response to a request, sent as one line of JSON.
+///
+/// A single request can produce any number of responses, and the connection
+/// is closed once there are no more.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Response {
+    Ok {
+        message: String,
+    },
+    Status {
+        tasks: Vec<TaskStatus>,
+    },
+    Line(Line),
+    /// A run started by [`Command::RunOnce`] has finished.
+    Finished(LastRun),
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskStatus {
+    pub task: String,
+    pub name: Option<String>,
+    pub state: TaskState,
+    /// The pid of the running process.
+    pub pid: Option<u32>,
+    /// When the running process was started.
+    pub started: Option<DateTime<Local>>,
+    /// The most recent run that has finished.
+    pub last: Option<LastRun>,
+    /// When the task will next be run by its cron.
+    pub next: Option<DateTime<Local>>,
+    /// How many times the task has been restarted.
+    pub restarts: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TaskState {
+    Idle,
+    /// Running, but its `ready` check hasn't passed yet.
+    Starting,
+    Running,
+    /// Waiting for a concurrency limit to allow it to start.
+    Queued,
+    Stopping,
+    /// Idle, but the last run was not successful.
+    Failed,
+    Disabled,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LastRun {
+    pub run: u64,
+    pub success: bool,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    pub duration_ms: u64,
+    pub finished: DateTime<Local>,
+}
+
+impl Control {
+    /// The socket path to use, given the path of the config file.
+    pub fn socket_path(&self, config: &Path) -> PathBuf {
+        self.socket
+            .clone()
+            .unwrap_or_else(|| config.with_extension("sock"))
+    }
+}
+
+/// Starts listening for requests on the given socket, forwarding them to the scheduler.
+pub fn listen(path: &Path, control: &Control, tx: Sender<Event>) -> eyre::Result<()> {
+    if path.exists() {
+        if is_running(path) {
+            eyre::bail!("servum is already running on `{}`", path.display());
+        }
+        fs::remove_file(path)
+            .wrap_err_with(|| format!("Failed to remove stale socket `{}`", path.display()))?;
+    }
+
+    let listener = UnixListener::bind(path)
+        .wrap_err_with(|| format!("Failed to bind socket `{}`", path.display()))?;
+    fs::set_permissions(path, Permissions::from_mode(control.mode))
+        .wrap_err_with(|| format!("Failed to set permissions of `{}`", path.display()))?;
+
+    let control = control.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let tx = tx.clone();
+                    let control = control.clone();
+                    thread::spawn(move || {
+                        if let Err(err) = serve(stream, &control, &tx) {
+                            warn!(
+                                event = "control.failed",
+                                error = %err,
+                                "Control connection failed: {err}"
+                            );
+                        }
+                    });
+                }
+                Err(err) => warn!(
+                    event = "control.accept_failed",
+                    error = %err,
+                    "Failed to accept control connection: {err}"
+                ),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn serve(mut stream: UnixStream, control: &Control, tx: &Sender<Event>) -> io::Result<()> {
+    let (uid, gids) = peer_credentials(&stream)?;
+    if !allowed(control, uid, &gids) {
+        warn!(
+            event = "control.rejected",
+            uid,
+            gid = gids[0],
+            "Rejected control connection"
+        );
+        return send(
+            &mut stream,
+            &Response::Error {
+                message: "Permission denied".to_owned(),
+            },
+        );
+    }
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let request = match serde_json::from_str::<Request>(&line) {
+        Ok(request) if request.version == VERSION => request,
+        Ok(request) => {
+            return send(
+                &mut stream,
+                &Response::Error {
+                    message: format!(
+                        "Unsupported protocol version {} (expected {VERSION})",
+                        request.version
+                    ),
+                },
+            );
+        }
+        Err(err) => {
+            return send(
+                &mut stream,
+                &Response::Error {
+                    message: format!("Invalid request: {err}"),
+                },
+            );
+        }
+    };
+
+    let (reply, rx) = mpsc::channel();
+    if tx
+        .send(Event::Control {
+            command: request.command,
+            reply,
+        })
+        .is_err()
+    {
+        return Ok(());
+    }
+
+    for response in rx {
+        send(&mut stream, &response)?;
+    }
+
+    Ok(())
+}
+
+fn allowed(control: &Control, uid: u32, gids: &[u32]) -> bool {
+    // SAFETY: `geteuid` has no memory safety requirements.
+    let own = unsafe { libc::geteuid() };
+    uid == 0
+        || uid == own
+        || control.allow_uids.contains(&uid)
+        || gids.iter().any(|gid| control.allow_gids.contains(gid))
+}
+
+/// The uid of the process on the other end of the socket, along with its
+/// primary gid followed by its supplementary ones.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_credentials(stream: &UnixStream) -> io::Result<(u32, Vec<u32>)> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    #[allow(clippy::cast_possible_truncation)]
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    // SAFETY: `cred` and `len` are valid for writes and `len` is the size of `cred`.
+    let res = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&raw mut cred).cast(),
+            &raw mut len,
+        )
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // The process may have exited already, in which case only its primary
+    // group is known.
+    let mut gids = vec![cred.gid];
+    if let Ok(status) = fs::read_to_string(format!("/proc/{}/status", cred.pid)) {
+        gids.extend(supplementary_groups(&status));
+    }
+    Ok((cred.uid, gids))
+}
+
+/// The supplementary groups listed in a `/proc/<pid>/status` file.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn supplementary_groups(status: &str) -> Vec<u32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Groups:"))
+        .into_iter()
+        .flat_map(str::split_whitespace)
+        .filter_map(|gid| gid.parse().ok())
+        .collect()
+}
+
+/// The uid and gid of the process on the other end of the socket.
+///
+/// Only the primary group is known here.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_credentials(stream: &UnixStream) -> io::Result<(u32, Vec<u32>)> {
+    let (mut uid, mut gid) = (0, 0);
+
+    // SAFETY: `uid` and `gid` are valid for writes.
+    let res = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok((uid, vec![gid]))
+}
+
+fn send(stream: &mut UnixStream, response: &Response) -> io::Result<()> {
+    let mut json = serde_json::to_vec(response)?;
+    json.push(b'\n');
+    stream.write_all(&json)
+}
+
+/// Whether an instance is listening on the given socket.
+pub fn is_running(path: &Path) -> bool {
+    UnixStream::connect(path).is_ok()
+}
+
+/// Sends a request to a running instance, returning its responses as they arrive.
+pub fn request(
+    path: &Path,
+    command: Command,
+) -> eyre::Result<impl Iterator<Item = eyre::Result<Response>>> {
+    let mut stream = UnixStream::connect(path).wrap_err_with(|| {
+        format!(
+            "Failed to connect to `{}`, is servum running?",
+            path.display()
+        )
+    })?;
+
+    let mut json = serde_json::to_vec(&Request {
+        version: VERSION,
+        command,
+    })?;
+    json.push(b'\n');
+    stream.write_all(&json)?;
+
+    Ok(BufReader::new(stream).lines().map(|line| {
+        let line = line?;
+        Ok(serde_json::from_str(&line)?)
+    }))
+}
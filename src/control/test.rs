@@ -0,0 +1,78 @@
+use chrono::TimeZone;
+use pretty_assertions::assert_eq;
+
+use super::*;
+
+#[test]
+fn test_request_format() {
+    let request = Request {
+        version: VERSION,
+        command: Command::Restart {
+            task: "foo".to_owned(),
+        },
+    };
+
+    let json = serde_json::to_string(&request).unwrap();
+
+    assert_eq!(json, r#"{"version":1,"command":"restart","task":"foo"}"#);
+    assert_eq!(serde_json::from_str::<Request>(&json).unwrap(), request);
+}
+
+#[test]
+fn test_response_format() {
+    let response = Response::Status {
+        tasks: vec![TaskStatus {
+            task: "foo".to_owned(),
+            name: None,
+            state: TaskState::Failed,
+            pid: None,
+            started: None,
+            last: Some(LastRun {
+                run: 3,
+                success: false,
+                exit_code: Some(2),
+                signal: None,
+                duration_ms: 1500,
+                finished: Local.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap(),
+            }),
+            next: None,
+            restarts: 1,
+        }],
+    };
+
+    let json = serde_json::to_string(&response).unwrap();
+
+    assert_eq!(
+        json,
+        r#"{"type":"status","tasks":[{"task":"foo","name":null,"state":"failed","pid":null,"started":null,"last":{"run":3,"success":false,"exit_code":2,"signal":null,"duration_ms":1500,"finished":"#
+            .to_owned()
+            + &serde_json::to_string(&Local.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap()).unwrap()
+            + r#"},"next":null,"restarts":1}]}"#
+    );
+}
+
+#[test]
+fn test_allowed() {
+    let control = Control {
+        allow_uids: vec![1234],
+        allow_gids: vec![5678],
+        ..Default::default()
+    };
+    // SAFETY: `geteuid` has no memory safety requirements.
+    let own = unsafe { libc::geteuid() };
+
+    assert!(allowed(&control, 0, &[0]));
+    assert!(allowed(&control, own, &[0]));
+    assert!(allowed(&control, 1234, &[1]));
+    assert!(allowed(&control, 4321, &[5678]));
+    assert!(allowed(&control, 4321, &[1, 5678]));
+    assert!(!allowed(&Control::default(), 4321, &[5678]) || own == 4321);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn test_supplementary_groups() {
+    let status = "Name:\tservum\nUid:\t1000\t1000\t1000\t1000\nGroups:\t4 27 1000 \nNgid:\t0\n";
+    assert_eq!(supplementary_groups(status), [4, 27, 1000]);
+    assert_eq!(supplementary_groups("Groups:\n"), [] as [u32; 0]);
+}
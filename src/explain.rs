@@ -0,0 +1,313 @@
+#[cfg(test)]
+mod test;
+
+use std::{
+    fmt::{self, Write},
+    hash::{Hash, Hasher},
+};
+
+use color_eyre::eyre::{self, WrapErr};
+use hashbrown::HashMap;
+use serde::{
+    de::{self, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use toml::Spanned;
+
+use crate::config::{Config, Field, ResolvedTask};
+
+/// A resolved string, along with where it was set.
+///
+/// Only the value is compared, so that resolving behaves exactly as it does
+/// for plain strings.
+#[derive(Debug, Clone)]
+pub struct Traced {
+    pub value: String,
+    pub origin: Origin,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    /// The id of the task that set the value.
+    pub task: String,
+    /// The line of the config file the value is on.
+    pub line: Option<usize>,
+}
+
+impl PartialEq for Traced {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Eq for Traced {}
+
+impl Hash for Traced {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state);
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}, line {line}", self.task),
+            None => f.write_str(&self.task),
+        }
+    }
+}
+
+/// Resolves a task while tracing where every inherited value came from.
+pub fn resolve(config: Config, source: &str, id: &str) -> eyre::Result<ResolvedTask<Traced>> {
+    let lines = Lines::new(source).wrap_err("Failed to parse config")?;
+    let mut tasks = config.resolve_tasks(|task, field, value| Traced {
+        origin: Origin {
+            task: task.to_owned(),
+            line: lines.find(task, field, &value),
+        },
+        value,
+    })?;
+
+    tasks
+        .remove(id)
+        .ok_or_else(|| eyre::eyre!("Unknown task `{id}`"))
+}
+
+/// Renders a traced task as a tree, with the origin of each value alongside it.
+pub fn render(id: &str, task: &ResolvedTask<Traced>) -> String {
+    let title = match &task.config.name {
+        Some(name) => format!("{id} ({name})"),
+        None => id.to_owned(),
+    };
+
+    let shell = match &task.shell {
+        Some(shell) => Node::new(
+            "shell",
+            shell
+                .iter()
+                .map(|s| Node::leaf(format!("{}  ({})", s.value, s.origin)))
+                .collect(),
+        ),
+        None => Node::leaf("shell: not set"),
+    };
+
+    let path = match &task.path {
+        Some(path) => Node::new(
+            format!(
+                "path (apply: {})",
+                format!("{:?}", path.apply).to_lowercase()
+            ),
+            path.dirs
+                .iter()
+                .map(|d| Node::leaf(format!("{}  ({})", d.value, d.origin)))
+                .collect(),
+        ),
+        None => Node::leaf("path: not set"),
+    };
+
+    let env = match &task.env {
+        Some(env) => {
+            let mut vars: Vec<_> = env.vars.iter().collect();
+            vars.sort_by(|(a, _), (b, _)| a.value.cmp(&b.value));
+            Node::new(
+                format!("env (merge: {})", env.merge),
+                vars.into_iter()
+                    .map(|(key, v)| {
+                        Node::leaf(format!("{}={}  ({})", key.value, v.value, v.origin))
+                    })
+                    .collect(),
+            )
+        }
+        None => Node::leaf("env: not set"),
+    };
+
+    let mut out = String::new();
+    Node::new(title, vec![shell, path, env]).write(&mut out, "", "");
+    out
+}
+
+struct Node {
+    label: String,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn new(label: impl Into<String>, children: Vec<Node>) -> Self {
+        Self {
+            label: label.into(),
+            children,
+        }
+    }
+
+    fn leaf(label: impl Into<String>) -> Self {
+        Self::new(label, vec![])
+    }
+
+    fn write(&self, out: &mut String, first: &str, rest: &str) {
+        let _ = writeln!(out, "{first}{}", self.label);
+        for (i, child) in self.children.iter().enumerate() {
+            if i + 1 == self.children.len() {
+                child.write(out, &format!("{rest}└── "), &format!("{rest}    "));
+            } else {
+                child.write(out, &format!("{rest}├── "), &format!("{rest}│   "));
+            }
+        }
+    }
+}
+
+/// The lines that each inheritable value of every task is on.
+#[derive(Debug, Default)]
+struct Lines {
+    tasks: HashMap<String, TaskLines>,
+}
+
+#[derive(Debug, Default)]
+struct TaskLines {
+    shell: HashMap<String, usize>,
+    dirs: HashMap<String, usize>,
+    vars: HashMap<String, usize>,
+}
+
+impl Lines {
+    fn new(source: &str) -> Result<Self, toml::de::Error> {
+        let spans: SpannedConfig = toml::from_str(source)?;
+        let line = |offset: usize| source[..offset].matches('\n').count() + 1;
+
+        let tasks = spans
+            .tasks
+            .into_iter()
+            .map(|(id, task)| {
+                let mut lines = TaskLines::default();
+                if let Some(shell) = task.shell {
+                    let start = shell.span().start;
+                    match shell.into_inner() {
+                        Strings::Single(s) => {
+                            lines.shell.insert(s, line(start));
+                        }
+                        Strings::Multi(ss) => {
+                            for s in ss {
+                                let start = s.span().start;
+                                lines.shell.insert(s.into_inner(), line(start));
+                            }
+                        }
+                    }
+                }
+                for dir in task.path.dirs {
+                    let start = dir.span().start;
+                    lines.dirs.insert(dir.into_inner(), line(start));
+                }
+                for (key, value) in task.env.vars {
+                    lines.vars.insert(key, line(value.span().start));
+                }
+                (id, lines)
+            })
+            .collect();
+
+        Ok(Self { tasks })
+    }
+
+    fn find(&self, task: &str, field: Field, value: &str) -> Option<usize> {
+        let lines = self.tasks.get(task)?;
+        match field {
+            Field::Shell => lines.shell.get(value),
+            Field::PathDir => lines.dirs.get(value),
+            Field::EnvKey => lines.vars.get(value),
+            Field::EnvValue { key } => lines.vars.get(key),
+        }
+        .copied()
+    }
+}
+
+/// Just enough of the config to find where each inheritable value is.
+#[derive(Debug, Default, Deserialize)]
+struct SpannedConfig {
+    #[serde(default, rename = "task")]
+    tasks: HashMap<String, SpannedTask>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SpannedTask {
+    shell: Option<Spanned<Strings>>,
+    path: Section,
+    env: Section,
+}
+
+#[derive(Debug)]
+enum Strings {
+    Single(String),
+    Multi(Vec<Spanned<String>>),
+}
+
+/// A `path` or `env` table, which can also be a boolean.
+#[derive(Debug, Default)]
+struct Section {
+    dirs: Vec<Spanned<String>>,
+    vars: HashMap<String, Spanned<String>>,
+}
+
+impl<'de> Deserialize<'de> for Strings {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StringsVisitor;
+
+        impl<'de> Visitor<'de> for StringsVisitor {
+            type Value = Strings;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string or list of strings")
+            }
+
+            fn visit_bool<E: de::Error>(self, _: bool) -> Result<Strings, E> {
+                Ok(Strings::Multi(vec![]))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Strings, E> {
+                Ok(Strings::Single(v.to_owned()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Strings, A::Error> {
+                let mut values = vec![];
+                while let Some(value) = seq.next_element()? {
+                    values.push(value);
+                }
+                Ok(Strings::Multi(values))
+            }
+        }
+
+        deserializer.deserialize_any(StringsVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Section {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SectionVisitor;
+
+        impl<'de> Visitor<'de> for SectionVisitor {
+            type Value = Section;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a boolean or table")
+            }
+
+            fn visit_bool<E: de::Error>(self, _: bool) -> Result<Section, E> {
+                Ok(Section::default())
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Section, A::Error> {
+                let mut section = Section::default();
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "dirs" => section.dirs = map.next_value()?,
+                        "vars" => section.vars = map.next_value()?,
+                        _ => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                }
+                Ok(section)
+            }
+        }
+
+        deserializer.deserialize_any(SectionVisitor)
+    }
+}
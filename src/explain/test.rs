@@ -0,0 +1,87 @@
+use pretty_assertions::assert_eq;
+
+use super::*;
+
+const CONFIG: &str = "
+[task.foo]
+path.dirs = ['/bin']
+
+[task.foo.env.vars]
+FOO_ENV = 'foo env value'
+BAR_ENV = 'bar env value'
+
+[task.bar]
+extends = 'foo'
+shell = '/bin/bash'
+
+[task.bar.env.vars]
+BAR_ENV = 'overridden bar env'
+
+[task.baz.path]
+dirs = [
+    '/usr/bin',
+]
+
+[task.qoz]
+extends = ['bar', 'baz']
+";
+
+#[test]
+fn test_resolve() {
+    let config: Config = CONFIG.parse().unwrap();
+    let task = resolve(config, CONFIG, "qoz").unwrap();
+
+    let origin = |task: &str, line| Origin {
+        task: task.to_owned(),
+        line: Some(line),
+    };
+    let dirs: Vec<_> = task
+        .path
+        .unwrap()
+        .dirs
+        .into_iter()
+        .map(|d| (d.value, d.origin))
+        .collect();
+    let env = task.env.unwrap();
+    let var = |key: &str| {
+        let (_, value) = env.vars.iter().find(|(k, _)| k.value == key).unwrap();
+        (value.value.clone(), value.origin.clone())
+    };
+
+    assert_eq!(task.shell.unwrap()[0].origin, origin("bar", 11));
+    assert_eq!(
+        dirs,
+        [
+            ("/usr/bin".to_owned(), origin("baz", 18)),
+            ("/bin".to_owned(), origin("foo", 3)),
+        ]
+    );
+    assert_eq!(
+        var("BAR_ENV"),
+        ("overridden bar env".to_owned(), origin("bar", 14))
+    );
+    assert_eq!(
+        var("FOO_ENV"),
+        ("foo env value".to_owned(), origin("foo", 6))
+    );
+}
+
+#[test]
+fn test_render() {
+    let config: Config = CONFIG.parse().unwrap();
+    let task = resolve(config, CONFIG, "bar").unwrap();
+
+    assert_eq!(
+        render("bar", &task),
+        "\
+bar
+├── shell
+│   └── /bin/bash  (bar, line 11)
+├── path (apply: before)
+│   └── /bin  (foo, line 3)
+└── env (merge: true)
+    ├── BAR_ENV=overridden bar env  (bar, line 14)
+    └── FOO_ENV=foo env value  (foo, line 6)
+"
+    );
+}
@@ -0,0 +1,175 @@
+#[cfg(test)]
+mod test;
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local, TimeDelta};
+use color_eyre::eyre::{self, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::config::History;
+
+/// How often runs older than `max-age` are dropped, if there aren't enough runs
+/// to go over `keep` first.
+const PRUNE_EVERY: Duration = Duration::from_hours(1);
+
+/// A finished run of a task.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    pub run: u64,
+    pub task: String,
+    pub trigger: String,
+    /// Which attempt at running the task this was, starting from `1`.
+    pub attempt: u32,
+    /// When the run was due, for runs started by `cron`.
+    pub scheduled: Option<DateTime<Local>>,
+    pub started: DateTime<Local>,
+    pub finished: DateTime<Local>,
+    pub success: bool,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    pub duration_ms: u64,
+    pub output_tail: Vec<String>,
+}
+
+/// Which runs `servum history` should show.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub task: Option<String>,
+    pub failed: bool,
+    pub since: Option<DateTime<Local>>,
+}
+
+impl Query {
+    pub fn matches(&self, record: &Record) -> bool {
+        self.task.as_ref().is_none_or(|task| *task == record.task)
+            && !(self.failed && record.success)
+            && self.since.is_none_or(|since| record.started >= since)
+    }
+}
+
+impl History {
+    /// The history file to use, given the path of the config file.
+    pub fn file_path(&self, config: &Path) -> PathBuf {
+        self.file
+            .clone()
+            .unwrap_or_else(|| config.with_extension("history"))
+    }
+}
+
+/// The history file, which runs are appended to as they finish.
+pub struct Store {
+    path: PathBuf,
+    keep: usize,
+    max_age: Option<TimeDelta>,
+    /// How many runs are in the file.
+    len: usize,
+    /// When old runs were last dropped.
+    pruned: DateTime<Local>,
+    last_run: u64,
+}
+
+impl Store {
+    /// Opens the history file at `path`, dropping any runs that `config`
+    /// doesn't keep.
+    pub fn open(path: PathBuf, config: &History, now: DateTime<Local>) -> eyre::Result<Self> {
+        let max_age = config
+            .max_age
+            .and_then(|ms| TimeDelta::try_milliseconds(i64::try_from(ms).ok()?));
+        let mut store = Self {
+            path,
+            keep: config.keep,
+            max_age,
+            len: 0,
+            pruned: now,
+            last_run: 0,
+        };
+        let records = store.prune(now)?;
+        store.last_run = records.iter().map(|r| r.run).max().unwrap_or_default();
+        Ok(store)
+    }
+
+    /// The id of the latest run in the history, or `0` if it is empty.
+    pub fn last_run(&self) -> u64 {
+        self.last_run
+    }
+
+    /// Appends a finished run to the history.
+    pub fn record(&mut self, record: &Record, now: DateTime<Local>) -> eyre::Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .wrap_err_with(|| format!("Failed to write history `{}`", self.path.display()))?;
+        self.len += 1;
+        self.last_run = self.last_run.max(record.run);
+
+        // Rewriting the file for every run would be wasteful, so let it grow a
+        // little past `keep` first.
+        let expired = self.max_age.is_some() && now >= self.pruned + PRUNE_EVERY;
+        if self.len > self.keep + self.keep / 10 || expired {
+            self.prune(now)?;
+        }
+        Ok(())
+    }
+
+    /// Drops the runs that are too old or beyond `keep`, rewriting the file if
+    /// any were (or if it had lines that couldn't be parsed), and returns the rest.
+    fn prune(&mut self, now: DateTime<Local>) -> eyre::Result<Vec<Record>> {
+        let (mut records, lines) = read(&self.path)?;
+        if let Some(max_age) = self.max_age {
+            records.retain(|r| r.finished + max_age >= now);
+        }
+        records.drain(..records.len().saturating_sub(self.keep));
+
+        if records.len() != lines {
+            let mut contents = String::new();
+            for record in &records {
+                contents.push_str(&serde_json::to_string(record)?);
+                contents.push('\n');
+            }
+            let mut tmp = self.path.clone().into_os_string();
+            tmp.push(".tmp");
+            fs::write(&tmp, contents)
+                .and_then(|()| fs::rename(&tmp, &self.path))
+                .wrap_err_with(|| format!("Failed to write history `{}`", self.path.display()))?;
+        }
+        self.len = records.len();
+        self.pruned = now;
+        Ok(records)
+    }
+}
+
+/// Reads every run in the history file at `path`, oldest first.
+///
+/// Lines that can't be parsed, such as one left half-written by a crash, are
+/// skipped.
+pub fn load(path: &Path) -> eyre::Result<Vec<Record>> {
+    read(path).map(|(records, _)| records)
+}
+
+/// Reads every run in the history file at `path`, along with how many lines
+/// the file has.
+fn read(path: &Path) -> eyre::Result<(Vec<Record>, usize)> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok((vec![], 0)),
+        Err(err) => {
+            return Err(err)
+                .wrap_err_with(|| format!("Failed to read history `{}`", path.display()))
+        }
+    };
+    let records = contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    Ok((records, contents.lines().count()))
+}
@@ -0,0 +1,99 @@
+use chrono::TimeZone;
+use pretty_assertions::assert_eq;
+
+use super::*;
+
+fn at(hour: u32) -> DateTime<Local> {
+    Local.with_ymd_and_hms(2024, 3, 1, hour, 0, 0).unwrap()
+}
+
+fn record(run: u64, task: &str, success: bool, started: DateTime<Local>) -> Record {
+    Record {
+        run,
+        task: task.to_owned(),
+        trigger: "cron".to_owned(),
+        attempt: 1,
+        scheduled: Some(started),
+        started,
+        finished: started,
+        success,
+        exit_code: Some(i32::from(!success)),
+        signal: None,
+        duration_ms: 0,
+        output_tail: vec![],
+    }
+}
+
+fn runs(path: &Path) -> Vec<u64> {
+    load(path).unwrap().iter().map(|r| r.run).collect()
+}
+
+#[test]
+fn test_query() {
+    let records = [
+        record(1, "backup", true, at(1)),
+        record(2, "report", false, at(2)),
+        record(3, "backup", false, at(3)),
+    ];
+    let matching = |query: Query| -> Vec<_> {
+        records
+            .iter()
+            .filter(|r| query.matches(r))
+            .map(|r| r.run)
+            .collect()
+    };
+
+    assert_eq!(matching(Query::default()), [1, 2, 3]);
+    assert_eq!(
+        matching(Query {
+            task: Some("backup".to_owned()),
+            ..Query::default()
+        }),
+        [1, 3]
+    );
+    assert_eq!(
+        matching(Query {
+            failed: true,
+            since: Some(at(3)),
+            ..Query::default()
+        }),
+        [3]
+    );
+}
+
+#[test]
+fn test_store() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("servum.history");
+    let config = History {
+        file: None,
+        keep: 20,
+        max_age: Some(5 * 60 * 60 * 1000),
+    };
+
+    let mut store = Store::open(path.clone(), &config, at(1)).unwrap();
+    assert_eq!(store.last_run(), 0);
+    for run in 1..=21 {
+        store
+            .record(&record(run, "backup", true, at(1)), at(1))
+            .unwrap();
+    }
+    // Going just past `keep` doesn't rewrite the file straight away.
+    assert_eq!(runs(&path), (1..=21).collect::<Vec<_>>());
+
+    // A line left half-written is skipped.
+    let contents = fs::read_to_string(&path).unwrap();
+    fs::write(&path, contents + "{\"run\": 22, \"task\"").unwrap();
+    assert_eq!(runs(&path).len(), 21);
+
+    // Reopening drops the oldest runs along with the broken line, and later
+    // those older than `max-age`.
+    let mut store = Store::open(path.clone(), &config, at(3)).unwrap();
+    assert_eq!(store.last_run(), 21);
+    assert_eq!(runs(&path), (2..=21).collect::<Vec<_>>());
+    assert!(fs::read_to_string(&path).unwrap().ends_with('\n'));
+    store
+        .record(&record(22, "backup", true, at(6)), at(7))
+        .unwrap();
+    assert_eq!(runs(&path), [22]);
+}
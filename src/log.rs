@@ -0,0 +1,518 @@
+#[cfg(test)]
+mod test;
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    thread::{self, JoinHandle},
+};
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, Timelike};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::config::{Log, LogTarget, Rotate, RotatePeriod};
+
+/// How `{date}` is formatted in log file paths.
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The resolved output destinations for a single task.
+///
+/// Sinks are shared between every run of the task, so that concurrent
+/// writers append to the same (rotated) files.
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub stdout: Sink,
+    pub stderr: Sink,
+    pub buffer: Arc<Mutex<Buffer>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Sink {
+    /// Write to servum's own stream, with each line prefixed by a label if set.
+    Inherit(Option<Arc<str>>),
+    Discard,
+    File(Arc<Mutex<LogFile>>),
+}
+
+impl Output {
+    pub fn new(task: &str, log: Option<&Log>) -> Self {
+        let default = Log::default();
+        let log = log.unwrap_or(&default);
+        let rotate = log.rotate.as_ref();
+
+        let stdout = Sink::new(task, log.stdout.as_ref(), rotate);
+        let stderr = if log.merge_stderr.unwrap_or(false) {
+            stdout.clone()
+        } else {
+            Sink::new(task, log.stderr.as_ref(), rotate)
+        };
+
+        Self {
+            stdout,
+            stderr,
+            buffer: Arc::new(Mutex::new(Buffer::new(log.buffer.unwrap_or(1000)))),
+        }
+    }
+
+    /// Prefixes every line written to servum's own stdout/stderr with `label`.
+    pub fn label(&mut self, label: &str) {
+        for sink in [&mut self.stdout, &mut self.stderr] {
+            if let Sink::Inherit(current) = sink {
+                *current = Some(label.into());
+            }
+        }
+    }
+
+    /// Carries over the buffered output from a previous version of this task.
+    pub fn keep_buffer(&mut self, old: &Output) {
+        let capacity = self
+            .buffer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .capacity;
+        self.buffer = old.buffer.clone();
+        self.buffer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .resize(capacity);
+    }
+
+    /// The text of the last `count` buffered lines of a run.
+    pub fn tail(&self, run: u64, count: usize) -> Vec<String> {
+        let buffer = self.buffer.lock().unwrap_or_else(PoisonError::into_inner);
+        let lines: Vec<_> = buffer.lines(Some(run)).collect();
+        lines[lines.len().saturating_sub(count)..]
+            .iter()
+            .map(|l| l.text.clone())
+            .collect()
+    }
+
+    /// Forwards each line read from `stream` to the relevant sink and the buffer
+    /// until the stream closes, which is when the returned thread finishes.
+    pub fn pipe(
+        &self,
+        task: &str,
+        run: u64,
+        kind: Stream,
+        stream: impl Read + Send + 'static,
+    ) -> JoinHandle<()> {
+        let sink = match kind {
+            Stream::Stdout => self.stdout.clone(),
+            Stream::Stderr => self.stderr.clone(),
+        };
+        let buffer = self.buffer.clone();
+        let task = task.to_owned();
+
+        thread::spawn(move || {
+            let reader = BufReader::new(stream);
+            for line in reader.split(b'\n') {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(err) => {
+                        warn!(
+                            event = "task.output_read_failed",
+                            task,
+                            error = %err,
+                            "Failed to read output of task `{task}`: {err}"
+                        );
+                        return;
+                    }
+                };
+                let now = Local::now();
+
+                if let Err(err) = sink.write_line(kind, &line, now) {
+                    warn!(
+                        event = "task.output_write_failed",
+                        task,
+                        error = %err,
+                        "Failed to write output of task `{task}`: {err}"
+                    );
+                }
+
+                buffer
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(Line {
+                        run,
+                        time: now,
+                        stream: kind,
+                        text: String::from_utf8_lossy(&line).into_owned(),
+                    });
+            }
+        })
+    }
+}
+
+impl Sink {
+    fn new(task: &str, target: Option<&LogTarget>, rotate: Option<&Rotate>) -> Self {
+        match target {
+            None | Some(LogTarget::Inherit) => Self::Inherit(None),
+            Some(LogTarget::Discard) => Self::Discard,
+            Some(LogTarget::File(template)) => Self::File(Arc::new(Mutex::new(LogFile::new(
+                task,
+                template,
+                rotate.cloned(),
+            )))),
+        }
+    }
+
+    fn write_line(&self, kind: Stream, line: &[u8], now: DateTime<Local>) -> io::Result<()> {
+        match self {
+            Self::Inherit(label) => {
+                let mut out: Box<dyn Write> = match kind {
+                    Stream::Stdout => Box::new(io::stdout().lock()),
+                    Stream::Stderr => Box::new(io::stderr().lock()),
+                };
+                // Written in one go, so that lines from different tasks can't interleave.
+                if let Some(label) = label {
+                    return out.write_all(&labelled(label, line, now));
+                }
+                out.write_all(line)?;
+                out.write_all(b"\n")
+            }
+            Self::Discard => Ok(()),
+            Self::File(file) => file
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .write_line(line, now),
+        }
+    }
+}
+
+/// Labels the output of each task when running in the foreground, so that the
+/// interleaved output of every task can be told apart.
+#[derive(Debug, Clone)]
+pub struct Foreground {
+    width: usize,
+    color: bool,
+    ids: Vec<String>,
+}
+
+/// ANSI colours for labels, avoiding black and white.
+const COLORS: [u8; 6] = [36, 33, 32, 35, 34, 31];
+
+impl Foreground {
+    pub fn new<'a>(ids: impl IntoIterator<Item = &'a String>, color: bool) -> Self {
+        let mut ids: Vec<_> = ids.into_iter().cloned().collect();
+        ids.sort();
+        Self {
+            width: ids.iter().map(String::len).max().unwrap_or(0),
+            color,
+            ids,
+        }
+    }
+
+    /// The label for a task, padded so that the output of every task lines up.
+    pub fn label(&self, task: &str) -> String {
+        let padded = format!("{task:<width$}", width = self.width);
+        if !self.color {
+            return padded;
+        }
+
+        // Tasks added later don't have a place in the order, so just pick one.
+        let index = self
+            .ids
+            .binary_search_by(|id| id.as_str().cmp(task))
+            .unwrap_or_else(|_| task.bytes().map(usize::from).sum());
+        format!("\x1b[{}m{padded}\x1b[0m", COLORS[index % COLORS.len()])
+    }
+}
+
+/// A line of output prefixed with the time and a label, e.g. `12:00:00 web | hello`.
+fn labelled(label: &str, line: &[u8], now: DateTime<Local>) -> Vec<u8> {
+    let mut out = format!("{} {label} | ", now.format("%H:%M:%S")).into_bytes();
+    out.extend_from_slice(line);
+    out.push(b'\n');
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// A single line of output from a task.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Line {
+    /// The id of the run that produced the line.
+    pub run: u64,
+    /// When the line was read.
+    pub time: DateTime<Local>,
+    pub stream: Stream,
+    pub text: String,
+}
+
+/// A ring buffer of the most recent lines of output from a task.
+///
+/// Anything following the output is sent each new line as it arrives.
+pub struct Buffer {
+    lines: VecDeque<Line>,
+    capacity: usize,
+    /// Each follower, along with the run it is limited to.
+    followers: Vec<(Option<u64>, Follower)>,
+}
+
+/// Called with every new line, returning whether it wants to keep following.
+type Follower = Box<dyn FnMut(&Line) -> bool + Send>;
+
+impl Buffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(capacity.min(1024)),
+            capacity,
+            followers: vec![],
+        }
+    }
+
+    /// Changes how many lines are kept, dropping the oldest if needed.
+    pub fn resize(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.lines.len() > capacity {
+            self.lines.pop_front();
+        }
+    }
+
+    pub fn push(&mut self, line: Line) {
+        self.followers
+            .retain_mut(|(run, f)| run.is_some_and(|r| r != line.run) || f(&line));
+
+        if self.capacity == 0 {
+            return;
+        }
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    /// The buffered lines, optionally only from the given run.
+    pub fn lines(&self, run: Option<u64>) -> impl Iterator<Item = &Line> {
+        self.lines
+            .iter()
+            .filter(move |l| run.is_none_or(|r| l.run == r))
+    }
+
+    /// Calls `f` with every new line until it returns `false`.
+    ///
+    /// If a run is given, then only lines from that run are passed to `f`, and it
+    /// is dropped once the run has [ended](Self::end).
+    pub fn follow(&mut self, run: Option<u64>, f: impl FnMut(&Line) -> bool + Send + 'static) {
+        self.followers.push((run, Box::new(f)));
+    }
+
+    /// Drops everything following the given run, as it won't produce any more output.
+    pub fn end(&mut self, run: u64) {
+        self.followers.retain(|(r, _)| *r != Some(run));
+    }
+}
+
+impl fmt::Debug for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Buffer")
+            .field("lines", &self.lines)
+            .field("capacity", &self.capacity)
+            .field("followers", &self.followers.len())
+            .finish()
+    }
+}
+
+/// An append-only log file that rotates itself according to the task config.
+///
+/// The file is only opened once something is written to it, so tasks that never
+/// produce output never create empty log files.
+#[derive(Debug)]
+pub struct LogFile {
+    template: String,
+    rotate: Option<Rotate>,
+    current: Option<Current>,
+}
+
+#[derive(Debug)]
+struct Current {
+    file: File,
+    path: PathBuf,
+    size: u64,
+    opened: DateTime<Local>,
+}
+
+impl LogFile {
+    pub fn new(task: &str, template: &str, rotate: Option<Rotate>) -> Self {
+        Self {
+            template: template.replace("{task}", task),
+            rotate,
+            current: None,
+        }
+    }
+
+    /// Writes a single line to the file, rotating beforehand if needed.
+    pub fn write_line(&mut self, line: &[u8], now: DateTime<Local>) -> io::Result<()> {
+        if self.should_rotate(now) {
+            self.rotate(now)?;
+        }
+
+        let current = match &mut self.current {
+            Some(current) => current,
+            None => self.current.insert(self.open(now)?),
+        };
+
+        current.file.write_all(line)?;
+        current.file.write_all(b"\n")?;
+        current.size += line.len() as u64 + 1;
+
+        Ok(())
+    }
+
+    fn path(&self, now: DateTime<Local>) -> PathBuf {
+        self.template
+            .replace("{date}", &now.format(DATE_FORMAT).to_string())
+            .into()
+    }
+
+    fn open(&self, now: DateTime<Local>) -> io::Result<Current> {
+        let path = self.path(now);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Current {
+            file,
+            path,
+            size,
+            opened: now,
+        })
+    }
+
+    fn should_rotate(&self, now: DateTime<Local>) -> bool {
+        let Some(current) = &self.current else {
+            return false;
+        };
+        // A `{date}` in the path moves on to a new file every day, whether or
+        // not the file is rotated otherwise.
+        if current.path != self.path(now) {
+            return true;
+        }
+        let Some(rotate) = &self.rotate else {
+            return false;
+        };
+
+        let too_big = rotate.size.is_some_and(|size| current.size >= size);
+        let new_period = rotate
+            .every
+            .is_some_and(|every| period(every, current.opened) != period(every, now));
+
+        too_big || new_period
+    }
+
+    fn rotate(&mut self, now: DateTime<Local>) -> io::Result<()> {
+        let Some(current) = self.current.take() else {
+            return Ok(());
+        };
+        let keep = self.rotate.as_ref().map_or(0, |r| r.keep);
+        drop(current.file);
+
+        if current.path == self.path(now) {
+            // Same file name, so shift the numbered backups along and drop the oldest.
+            for n in (1..keep).rev() {
+                let from = numbered(&current.path, n);
+                if from.exists() {
+                    fs::rename(from, numbered(&current.path, n + 1))?;
+                }
+            }
+
+            if keep == 0 {
+                fs::remove_file(&current.path)?;
+            } else {
+                fs::rename(&current.path, numbered(&current.path, 1))?;
+            }
+        } else if self.rotate.is_some() {
+            // The file name is dated, so a new file will be opened and old ones cleaned up.
+            self.prune_dated(keep)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes all but the newest `keep` previous files matching a `{date}` template.
+    fn prune_dated(&self, keep: usize) -> io::Result<()> {
+        let template = Path::new(&self.template);
+        let (Some(dir), Some(name)) = (template.parent(), template.file_name()) else {
+            return Ok(());
+        };
+        let name = name.to_string_lossy();
+        let Some((prefix, suffix)) = name.split_once("{date}") else {
+            return Ok(());
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+
+        let old: Vec<_> = fs::read_dir(dir)?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter_map(|path| {
+                let date = dated(&path.file_name()?.to_string_lossy(), prefix, suffix)?;
+                Some((date, path))
+            })
+            .collect();
+        let mut dates: Vec<_> = old.iter().map(|(date, _)| *date).collect();
+        dates.sort();
+        dates.dedup();
+
+        // Each day's numbered backups go along with its file.
+        let excess = dates.len().saturating_sub(keep);
+        if excess == 0 {
+            return Ok(());
+        }
+        let oldest_kept = dates.get(excess).copied();
+        for (date, path) in old {
+            if oldest_kept.is_none_or(|kept| date < kept) {
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The date of a file named by a `{date}` template, or of a numbered backup of
+/// one.
+///
+/// Only the date itself is matched, so that the files of tasks whose ids start
+/// with this one's aren't mistaken for its own.
+fn dated(name: &str, prefix: &str, suffix: &str) -> Option<NaiveDate> {
+    let name = match name.rsplit_once('.') {
+        Some((name, n)) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => name,
+    };
+    let date = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    NaiveDate::parse_from_str(date, DATE_FORMAT).ok()
+}
+
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{n}"));
+    path.into()
+}
+
+fn period(every: RotatePeriod, time: DateTime<Local>) -> (i32, u32, u32) {
+    match every {
+        RotatePeriod::Hourly => (time.year(), time.ordinal(), time.hour()),
+        RotatePeriod::Daily => (time.year(), time.ordinal(), 0),
+        RotatePeriod::Weekly => {
+            let week = time.iso_week();
+            (week.year(), week.week(), 0)
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::fs;
+
+use chrono::{Local, TimeZone};
+use pretty_assertions::assert_eq;
+
+use super::*;
+
+fn at(day: u32, hour: u32) -> DateTime<Local> {
+    Local.with_ymd_and_hms(2024, 3, day, hour, 0, 0).unwrap()
+}
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn test_templated_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let template = dir.path().join("{task}/{date}.log");
+    let mut file = LogFile::new("foo", template.to_str().unwrap(), None);
+
+    file.write_line(b"hello", at(1, 0)).unwrap();
+    file.write_line(b"again", at(1, 12)).unwrap();
+    file.write_line(b"world", at(2, 0)).unwrap();
+    file.write_line(b"more", at(3, 0)).unwrap();
+
+    // Even without rotation, each day gets its own file, and none are removed.
+    assert_eq!(
+        read(&dir.path().join("foo/2024-03-01.log")),
+        "hello\nagain\n"
+    );
+    assert_eq!(read(&dir.path().join("foo/2024-03-02.log")), "world\n");
+    assert_eq!(read(&dir.path().join("foo/2024-03-03.log")), "more\n");
+}
+
+#[test]
+fn test_rotate_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out.log");
+    let rotate = Rotate {
+        size: Some(6),
+        keep: 2,
+        ..Default::default()
+    };
+    let mut file = LogFile::new("foo", path.to_str().unwrap(), Some(rotate));
+
+    for line in ["one", "two", "three", "four", "five", "six"] {
+        file.write_line(line.as_bytes(), at(1, 0)).unwrap();
+    }
+
+    assert_eq!(read(&path), "six\n");
+    assert_eq!(read(&numbered(&path, 1)), "four\nfive\n");
+    assert_eq!(read(&numbered(&path, 2)), "three\n");
+    assert!(!numbered(&path, 3).exists());
+}
+
+#[test]
+fn test_rotate_period() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out.log");
+    let rotate = Rotate {
+        every: Some(RotatePeriod::Hourly),
+        ..Default::default()
+    };
+    let mut file = LogFile::new("foo", path.to_str().unwrap(), Some(rotate));
+
+    file.write_line(b"one", at(1, 0)).unwrap();
+    file.write_line(b"two", at(1, 0)).unwrap();
+    file.write_line(b"three", at(1, 1)).unwrap();
+
+    assert_eq!(read(&path), "three\n");
+    assert_eq!(read(&numbered(&path, 1)), "one\ntwo\n");
+}
+
+#[test]
+fn test_rotate_dated() {
+    let dir = tempfile::tempdir().unwrap();
+    let template = dir.path().join("{task}-{date}.log");
+    let rotate = Rotate {
+        every: Some(RotatePeriod::Daily),
+        keep: 2,
+        ..Default::default()
+    };
+    let mut file = LogFile::new("foo", template.to_str().unwrap(), Some(rotate));
+
+    for day in 1..=4 {
+        file.write_line(b"line", at(day, 0)).unwrap();
+    }
+
+    let mut names: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+
+    assert_eq!(
+        names,
+        [
+            "foo-2024-03-02.log",
+            "foo-2024-03-03.log",
+            "foo-2024-03-04.log"
+        ]
+    );
+}
+
+#[test]
+fn test_rotate_dated_backups() {
+    let dir = tempfile::tempdir().unwrap();
+    let template = dir.path().join("{task}-{date}.log");
+    let rotate = Rotate {
+        size: Some(5),
+        keep: 2,
+        ..Default::default()
+    };
+    let mut file = LogFile::new("foo", template.to_str().unwrap(), Some(rotate));
+
+    for day in 1..=4 {
+        file.write_line(b"line", at(day, 0)).unwrap();
+        file.write_line(b"line", at(day, 1)).unwrap();
+    }
+
+    let mut names: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+
+    // The first day's backup is removed along with its file.
+    assert_eq!(
+        names,
+        [
+            "foo-2024-03-02.log",
+            "foo-2024-03-02.log.1",
+            "foo-2024-03-03.log",
+            "foo-2024-03-03.log.1",
+            "foo-2024-03-04.log",
+            "foo-2024-03-04.log.1",
+        ]
+    );
+}
+
+#[test]
+fn test_rotate_dated_shared_prefix() {
+    let dir = tempfile::tempdir().unwrap();
+    let template = dir.path().join("{task}-{date}.log");
+    let rotate = Rotate {
+        every: Some(RotatePeriod::Daily),
+        keep: 1,
+        ..Default::default()
+    };
+    let mut foo = LogFile::new("foo", template.to_str().unwrap(), Some(rotate.clone()));
+    let mut foo_bar = LogFile::new("foo-bar", template.to_str().unwrap(), Some(rotate));
+
+    for day in 1..=3 {
+        foo_bar.write_line(b"line", at(day, 0)).unwrap();
+    }
+    for day in 1..=3 {
+        foo.write_line(b"line", at(day, 0)).unwrap();
+    }
+
+    let mut names: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+
+    assert_eq!(
+        names,
+        [
+            "foo-2024-03-02.log",
+            "foo-2024-03-03.log",
+            "foo-bar-2024-03-02.log",
+            "foo-bar-2024-03-03.log",
+        ]
+    );
+}
+
+fn line(run: u64, text: &str) -> Line {
+    Line {
+        run,
+        time: at(1, 0),
+        stream: Stream::Stdout,
+        text: text.to_owned(),
+    }
+}
+
+#[test]
+fn test_foreground_label() {
+    let ids = ["web".to_owned(), "worker".to_owned(), "db".to_owned()];
+    let plain = Foreground::new(&ids, false);
+    assert_eq!(plain.label("db"), "db    ");
+    assert_eq!(plain.label("worker"), "worker");
+    assert_eq!(plain.label("a-new-task"), "a-new-task");
+
+    let colored = Foreground::new(&ids, true);
+    assert_eq!(colored.label("db"), "\x1b[36mdb    \x1b[0m");
+    assert_eq!(colored.label("web"), "\x1b[33mweb   \x1b[0m");
+
+    assert_eq!(
+        labelled("web   ", b"hello", at(1, 12)),
+        b"12:00:00 web    | hello\n"
+    );
+}
+
+#[test]
+fn test_buffer() {
+    let mut buffer = Buffer::new(3);
+    for (run, text) in [(1, "a"), (1, "b"), (2, "c"), (2, "d")] {
+        buffer.push(line(run, text));
+    }
+
+    let all: Vec<_> = buffer.lines(None).map(|l| l.text.as_str()).collect();
+    let run: Vec<_> = buffer.lines(Some(2)).map(|l| l.text.as_str()).collect();
+
+    assert_eq!(all, ["b", "c", "d"]);
+    assert_eq!(run, ["c", "d"]);
+}
+
+#[test]
+fn test_buffer_follow() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut buffer = Buffer::new(0);
+    buffer.follow(None, move |l| tx.send(l.text.clone()).is_ok());
+
+    buffer.push(line(1, "a"));
+    buffer.push(line(1, "b"));
+    let followed: Vec<_> = rx.try_iter().collect();
+    drop(rx);
+    buffer.push(line(1, "c"));
+
+    assert_eq!(followed, ["a", "b"]);
+    assert_eq!(buffer.lines(None).count(), 0);
+    assert_eq!(buffer.followers.len(), 0);
+}
+
+#[test]
+fn test_buffer_follow_run() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut buffer = Buffer::new(0);
+    buffer.follow(Some(2), move |l| tx.send(l.text.clone()).is_ok());
+
+    buffer.push(line(1, "a"));
+    buffer.push(line(2, "b"));
+    buffer.end(2);
+    buffer.push(line(2, "c"));
+
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), ["b"]);
+    assert_eq!(buffer.followers.len(), 0);
+}
@@ -0,0 +1,127 @@
+#[cfg(test)]
+mod test;
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    thread,
+};
+
+use chrono::{DateTime, Local};
+use color_eyre::eyre::{self, WrapErr};
+use tracing::warn;
+
+use crate::{
+    config::{MailOn, MultiStr},
+    control::LastRun,
+    table,
+};
+
+/// The `sendmail` command used if a task doesn't set one.
+const SENDMAIL: [&str; 3] = ["/usr/sbin/sendmail", "-oi", "-t"];
+
+/// Whether a finished run should be emailed, according to `mail-on`.
+pub fn wanted(on: MailOn, last: &LastRun, output: &[String]) -> bool {
+    match on {
+        MailOn::Output => !output.is_empty(),
+        MailOn::Failure => !last.success,
+        MailOn::Always => true,
+    }
+}
+
+/// Composes an email with the result and output of a run.
+pub fn compose(
+    id: &str,
+    to: &str,
+    from: &str,
+    last: &LastRun,
+    output: &[String],
+    now: DateTime<Local>,
+) -> String {
+    let status = match (last.exit_code, last.signal) {
+        (Some(code), _) => format!("exit status {code}"),
+        (_, Some(signal)) => format!("killed by signal {signal}"),
+        _ => "unknown status".to_owned(),
+    };
+    let result = if last.success { "succeeded" } else { "failed" };
+
+    let mut message = format!(
+        "From: {from}\n\
+         To: {to}\n\
+         Subject: servum: task `{id}` {result} ({status})\n\
+         Date: {}\n\
+         MIME-Version: 1.0\n\
+         Content-Type: text/plain; charset=utf-8\n\
+         Content-Transfer-Encoding: 8bit\n\
+         Auto-Submitted: auto-generated\n\
+         \n\
+         Task: {id}\n\
+         Run: {}\n\
+         Status: {status}\n\
+         Duration: {}\n",
+        now.to_rfc2822(),
+        last.run,
+        table::duration(last.duration_ms)
+    );
+    if !output.is_empty() {
+        message.push('\n');
+        for line in output {
+            message.push_str(line);
+            message.push('\n');
+        }
+    }
+    message
+}
+
+/// The argv to run for a task's `sendmail`.
+pub fn argv(sendmail: Option<&MultiStr>) -> Vec<String> {
+    match sendmail {
+        Some(MultiStr::Single(cmd)) => cmd.split_whitespace().map(ToOwned::to_owned).collect(),
+        Some(MultiStr::Multi(argv)) => argv.clone(),
+        None => SENDMAIL.map(ToOwned::to_owned).to_vec(),
+    }
+}
+
+/// Pipes an email into `sendmail` in the background, with `mail.failed` logged if
+/// it doesn't succeed.
+pub fn send(id: &str, run: u64, sendmail: Vec<String>, message: String) {
+    let id = id.to_owned();
+    thread::spawn(move || {
+        if let Err(err) = pipe(&sendmail, &message) {
+            warn!(
+                event = "mail.failed",
+                task = id,
+                run,
+                error = format!("{err:#}"),
+                "Failed to email the output of task `{id}`: {err:#}"
+            );
+        }
+    });
+}
+
+/// Runs `sendmail` with the email as its stdin, waiting for it to exit.
+fn pipe(sendmail: &[String], message: &str) -> eyre::Result<()> {
+    let Some((program, args)) = sendmail.split_first() else {
+        eyre::bail!("`sendmail` is empty");
+    };
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .wrap_err_with(|| format!("Failed to run `{program}`"))?;
+
+    let written = child
+        .stdin
+        .take()
+        .map(|mut stdin| stdin.write_all(message.as_bytes()));
+    let status = child.wait()?;
+    if let Some(written) = written {
+        written.wrap_err_with(|| format!("Failed to write to `{program}`"))?;
+    }
+    if !status.success() {
+        eyre::bail!("`{program}` failed ({status})");
+    }
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use std::{fs, os::unix::fs::PermissionsExt};
+
+use chrono::TimeZone;
+use pretty_assertions::assert_eq;
+
+use super::*;
+
+fn last(success: bool) -> LastRun {
+    LastRun {
+        run: 7,
+        success,
+        exit_code: Some(u8::from(!success).into()),
+        signal: None,
+        duration_ms: 1500,
+        finished: Local.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap(),
+    }
+}
+
+#[test]
+fn test_wanted() {
+    let output = ["copied 3 files".to_owned()];
+    assert!(wanted(MailOn::Output, &last(true), &output));
+    assert!(!wanted(MailOn::Output, &last(false), &[]));
+    assert!(!wanted(MailOn::Failure, &last(true), &output));
+    assert!(wanted(MailOn::Failure, &last(false), &[]));
+    assert!(wanted(MailOn::Always, &last(true), &[]));
+}
+
+#[test]
+fn test_compose() {
+    let now = Local.with_ymd_and_hms(2024, 3, 1, 10, 0, 1).unwrap();
+    let message = compose(
+        "backup",
+        "ops@example.com",
+        "servum",
+        &last(false),
+        &["copying".to_owned(), "disk full".to_owned()],
+        now,
+    );
+
+    assert_eq!(
+        message,
+        format!(
+            "From: servum\n\
+             To: ops@example.com\n\
+             Subject: servum: task `backup` failed (exit status 1)\n\
+             Date: {}\n\
+             MIME-Version: 1.0\n\
+             Content-Type: text/plain; charset=utf-8\n\
+             Content-Transfer-Encoding: 8bit\n\
+             Auto-Submitted: auto-generated\n\
+             \n\
+             Task: backup\n\
+             Run: 7\n\
+             Status: exit status 1\n\
+             Duration: 1.5s\n\
+             \n\
+             copying\n\
+             disk full\n",
+            now.to_rfc2822()
+        )
+    );
+}
+
+#[test]
+fn test_pipe() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("sendmail");
+    let captured = dir.path().join("captured");
+    fs::write(
+        &script,
+        format!("#!/bin/sh\ncat > '{}'\n", captured.display()),
+    )
+    .unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let sendmail = argv(Some(&MultiStr::Single(format!("{} -t", script.display()))));
+    pipe(&sendmail, "To: ops@example.com\n\nhi\n").unwrap();
+    assert_eq!(
+        fs::read_to_string(&captured).unwrap(),
+        "To: ops@example.com\n\nhi\n"
+    );
+
+    let failing = vec!["false".to_owned()];
+    assert!(pipe(&failing, "").is_err());
+    assert_eq!(argv(None), ["/usr/sbin/sendmail", "-oi", "-t"]);
+}
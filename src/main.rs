@@ -1,13 +1,467 @@
 #![warn(clippy::pedantic)]
 
-use color_eyre::eyre;
+use std::{
+    env, fs,
+    io::{self, IsTerminal},
+    os::unix::process::{CommandExt, ExitStatusExt},
+    path::Path,
+};
 
+use chrono::{DateTime, Local};
+use clap::Parser;
+use color_eyre::eyre::{self, WrapErr};
+use hashbrown::HashMap;
+
+use crate::{
+    cli::{Cli, Command},
+    config::{Config, EventFormat, ResolvedTask},
+    control::Response,
+    explain::Traced,
+    log::{Foreground, Stream},
+    scheduler::Scheduler,
+    table::Table,
+};
+
+mod agenda;
 mod cli;
+mod clock;
 mod config;
+mod control;
+mod explain;
+mod history;
+mod log;
+mod mail;
+mod notification;
+mod probe;
+mod process;
+mod scheduler;
+mod simulate;
+mod state;
+mod table;
+mod watch;
 
 fn main() -> eyre::Result<()> {
     color_eyre::install()?;
-    println!("Hello, world!");
+    let cli = Cli::parse();
+
+    let config = load_config(&cli.config)?;
+    let control = config.control.clone();
+    let socket = control.socket_path(&cli.config);
+
+    let default = Command::Run {
+        foreground: false,
+        strict: false,
+    };
+    match cli.command.unwrap_or(default) {
+        Command::Run { foreground, strict } => {
+            init_events(cli.event_format.unwrap_or(config.events.format));
+            run(config, &cli.config, foreground, strict)
+        }
+        Command::Status { json } => status(&socket, json),
+        Command::Start { task } => simple(&socket, control::Command::Start { task }),
+        Command::Stop { task } => simple(&socket, control::Command::Stop { task }),
+        Command::Restart { task } => simple(&socket, control::Command::Restart { task }),
+        Command::Enable { task } => simple(&socket, control::Command::Enable { task }),
+        Command::Disable { task } => simple(&socket, control::Command::Disable { task }),
+        Command::Reload => simple(&socket, control::Command::Reload),
+        Command::Shutdown => simple(&socket, control::Command::Shutdown),
+        Command::Logs { task, follow, run } => logs(&socket, task, follow, run),
+        Command::RunOnce { task, local } => {
+            let code = if local || !control::is_running(&socket) {
+                run_local(config, &task)?
+            } else {
+                run_once(&socket, task)?
+            };
+            std::process::exit(code)
+        }
+        Command::Exec { task, cmd } => {
+            let task = resolve(config, &task)?;
+            let err = task.command(&cmd).exec();
+            Err(err).wrap_err_with(|| format!("Failed to run `{}`", cmd[0]))
+        }
+        Command::Env {
+            task,
+            json,
+            sources,
+        } => {
+            let source = fs::read_to_string(&cli.config)
+                .wrap_err_with(|| format!("Failed to read config `{}`", cli.config.display()))?;
+            print_env(&explain::resolve(config, &source, &task)?, json, sources)
+        }
+        Command::Explain { task } => {
+            let source = fs::read_to_string(&cli.config)
+                .wrap_err_with(|| format!("Failed to read config `{}`", cli.config.display()))?;
+            let resolved = explain::resolve(config, &source, &task)?;
+            print!("{}", explain::render(&task, &resolved));
+            Ok(())
+        }
+        Command::Next { task, count, until } => next(config, task.as_deref(), count, until),
+        Command::History {
+            task,
+            failed,
+            since,
+            json,
+        } => {
+            let query = history::Query {
+                task,
+                failed,
+                since,
+            };
+            history(&config.history.file_path(&cli.config), &query, json)
+        }
+        Command::Simulate {
+            from,
+            to,
+            durations,
+            default_duration,
+        } => {
+            let to = cli::parse_time_after(&to, from).map_err(|err| eyre::eyre!(err))?;
+            let concurrency = config.concurrency();
+            let (_, tasks) = config.try_into()?;
+            let durations = simulate::Durations {
+                tasks: durations.into_iter().collect(),
+                default: default_duration,
+                ..simulate::Durations::default()
+            };
+            let report =
+                simulate::simulate(cli.config.clone(), tasks, concurrency, from, to, durations)?;
+            print_report(report);
+            Ok(())
+        }
+    }
+}
+
+/// Runs the scheduler until it shuts down.
+fn run(config: Config, path: &Path, foreground: bool, strict: bool) -> eyre::Result<()> {
+    let control = config.control.clone();
+    let socket = control.socket_path(path);
+    let concurrency = config.concurrency();
+    let state = config.state_path(path);
+    let history = config.history.clone();
+    let (watch, tasks): (_, HashMap<_, _>) = config.try_into()?;
+    let labels = Foreground::new(tasks.keys(), io::stdout().is_terminal());
+
+    let mut scheduler = Scheduler::new(path.to_owned(), tasks, concurrency, &watch)?;
+    scheduler.persist(state)?;
+    if history.keep > 0 {
+        let store = history::Store::open(history.file_path(path), &history, Local::now())?;
+        scheduler.history(store);
+    }
+    if foreground {
+        scheduler.foreground(labels);
+    }
+    if strict {
+        scheduler.strict();
+    }
+    control::listen(&socket, &control, scheduler.sender())?;
+    let _watcher = watch::config(path, &watch, scheduler.sender())?;
+    let res = scheduler.run();
+    let _ = fs::remove_file(&socket);
+    res
+}
+
+/// Prints what happened during a simulation.
+fn print_report(report: simulate::Report) {
+    let mut table = Table::new(&["TIME", "EVENT", "MESSAGE"]);
+    for record in report.records {
+        table.row(vec![
+            record.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            record.event,
+            record.message,
+        ]);
+    }
+    print!("{}", table.render());
+
+    match report.peak.at {
+        Some(at) => println!(
+            "\nPeak concurrency: {} at {} ({})",
+            report.peak.running,
+            at.format("%Y-%m-%d %H:%M:%S"),
+            report.peak.tasks.join(", ")
+        ),
+        None => println!("\nNothing ran"),
+    }
+}
+
+/// Sends a command that only expects a single ok/error response.
+fn simple(socket: &Path, command: control::Command) -> eyre::Result<()> {
+    for response in control::request(socket, command)? {
+        match response? {
+            Response::Ok { message } => println!("{message}"),
+            Response::Error { message } => eyre::bail!(message),
+            other => eyre::bail!("Unexpected response: {other:?}"),
+        }
+    }
 
     Ok(())
 }
+
+fn status(socket: &Path, json: bool) -> eyre::Result<()> {
+    for response in control::request(socket, control::Command::Status)? {
+        let tasks = match response? {
+            Response::Status { tasks } => tasks,
+            Response::Error { message } => eyre::bail!(message),
+            other => eyre::bail!("Unexpected response: {other:?}"),
+        };
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&tasks)?);
+            continue;
+        }
+
+        let now = Local::now();
+        let mut table = Table::new(&[
+            "TASK", "STATE", "PID", "UPTIME", "LAST", "EXIT", "NEXT", "RESTARTS",
+        ]);
+        for task in tasks {
+            let uptime = task.started.map(|started| {
+                table::duration(u64::try_from((now - started).num_milliseconds()).unwrap_or(0))
+            });
+            let exit = task
+                .last
+                .as_ref()
+                .and_then(|last| match (last.exit_code, last.signal) {
+                    (Some(code), _) => Some(code.to_string()),
+                    (_, Some(signal)) => Some(format!("sig {signal}")),
+                    _ => None,
+                });
+
+            table.row(vec![
+                task.task,
+                serde_json::to_value(task.state)?
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_owned(),
+                task.pid.map(|p| p.to_string()).unwrap_or_default(),
+                uptime.unwrap_or_default(),
+                task.last
+                    .as_ref()
+                    .map(|last| table::duration(last.duration_ms))
+                    .unwrap_or_default(),
+                exit.unwrap_or_default(),
+                task.next
+                    .map(|next| next.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_default(),
+                task.restarts.to_string(),
+            ]);
+        }
+        print!("{}", table.render());
+    }
+
+    Ok(())
+}
+
+fn logs(socket: &Path, task: String, follow: bool, run: Option<u64>) -> eyre::Result<()> {
+    for response in control::request(socket, control::Command::Logs { task, run, follow })? {
+        match response? {
+            Response::Line(line) => {
+                let text = format!(
+                    "{} #{} {}",
+                    line.time.format("%Y-%m-%d %H:%M:%S"),
+                    line.run,
+                    line.text
+                );
+                match line.stream {
+                    Stream::Stdout => println!("{text}"),
+                    Stream::Stderr => eprintln!("{text}"),
+                }
+            }
+            Response::Error { message } => eyre::bail!(message),
+            other => eyre::bail!("Unexpected response: {other:?}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Asks a running instance to run a task, returning the exit code to use.
+fn run_once(socket: &Path, task: String) -> eyre::Result<i32> {
+    for response in control::request(socket, control::Command::RunOnce { task })? {
+        match response? {
+            Response::Ok { message } => eprintln!("{message}"),
+            Response::Line(line) => match line.stream {
+                Stream::Stdout => println!("{}", line.text),
+                Stream::Stderr => eprintln!("{}", line.text),
+            },
+            Response::Finished(last) => return Ok(exit_code(last.exit_code, last.signal)),
+            Response::Error { message } => eyre::bail!(message),
+            other @ Response::Status { .. } => eyre::bail!("Unexpected response: {other:?}"),
+        }
+    }
+
+    eyre::bail!("Connection closed before the task finished")
+}
+
+/// Runs a task directly, returning the exit code to use.
+fn run_local(config: Config, id: &str) -> eyre::Result<i32> {
+    let task = resolve(config, id)?;
+    if !task.config.enabled {
+        eyre::bail!("Task `{id}` is disabled");
+    }
+
+    let argv = task.argv()?;
+    let mut child = task
+        .command(&argv)
+        .spawn()
+        .wrap_err_with(|| format!("Failed to start task `{id}`"))?;
+
+    // Like a shell, leave interrupts to the task so that its exit code can be passed on.
+    // SAFETY: `signal` has no memory safety requirements.
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_IGN);
+        libc::signal(libc::SIGQUIT, libc::SIG_IGN);
+    }
+    let status = child
+        .wait()
+        .wrap_err_with(|| format!("Failed to wait on task `{id}`"))?;
+
+    Ok(exit_code(status.code(), status.signal()))
+}
+
+fn print_env(task: &ResolvedTask<Traced>, json: bool, sources: bool) -> eyre::Result<()> {
+    let vars = task.annotated_environment(env::vars_os());
+
+    if json {
+        let value: serde_json::Value = if sources {
+            vars.iter()
+                .map(|v| {
+                    serde_json::json!({
+                        "key": v.key.to_string_lossy(),
+                        "value": v.value.to_string_lossy(),
+                        "source": v.source.as_str(),
+                        "task": v.source.task(),
+                    })
+                })
+                .collect()
+        } else {
+            vars.iter()
+                .map(|v| {
+                    let key = v.key.to_string_lossy().into_owned();
+                    (key, v.value.to_string_lossy().into())
+                })
+                .collect::<serde_json::Map<_, _>>()
+                .into()
+        };
+        println!("{}", serde_json::to_string_pretty(&value)?);
+        return Ok(());
+    }
+
+    for v in vars {
+        let (key, value) = (v.key.to_string_lossy(), v.value.to_string_lossy());
+        if sources {
+            println!("{}\t{key}={value}", v.source);
+        } else {
+            println!("{key}={value}");
+        }
+    }
+
+    Ok(())
+}
+
+fn next(
+    config: Config,
+    task: Option<&str>,
+    count: Option<usize>,
+    until: Option<DateTime<Local>>,
+) -> eyre::Result<()> {
+    let (_, mut tasks): (_, HashMap<String, ResolvedTask>) = config.try_into()?;
+    if let Some(id) = task {
+        let task = tasks
+            .remove(id)
+            .ok_or_else(|| eyre::eyre!("Unknown task `{id}`"))?;
+        if task.config.cron.is_none() {
+            eyre::bail!("Task `{id}` has no cron");
+        }
+        tasks = HashMap::from([(id.to_owned(), task)]);
+    }
+
+    let now = Local::now();
+    let count = count.or(until.is_none().then_some(10));
+    let agenda = agenda::agenda(&tasks, now, count, until)?;
+
+    for id in &agenda.never {
+        eprintln!("Warning: the cron of task `{id}` will never fire");
+    }
+
+    let mut table = Table::new(&["TIME", "IN", "TASK", "NOTE"]);
+    for fire in agenda.fires {
+        let until = u64::try_from((fire.time - now).num_milliseconds()).unwrap_or(0);
+        table.row(vec![
+            fire.time.format("%Y-%m-%d %H:%M:%S %:z").to_string(),
+            table::duration(until),
+            fire.task,
+            if fire.enabled { "" } else { "disabled" }.to_owned(),
+        ]);
+    }
+    print!("{}", table.render());
+
+    Ok(())
+}
+
+/// Prints the runs in the history that match `query`, oldest first.
+fn history(path: &Path, query: &history::Query, json: bool) -> eyre::Result<()> {
+    let records: Vec<_> = history::load(path)?
+        .into_iter()
+        .filter(|r| query.matches(r))
+        .collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&records)?);
+        return Ok(());
+    }
+
+    let mut table = Table::new(&[
+        "RUN", "TASK", "TRIGGER", "STARTED", "DURATION", "EXIT", "SUCCESS",
+    ]);
+    for record in records {
+        let exit = match (record.exit_code, record.signal) {
+            (Some(code), _) => code.to_string(),
+            (_, Some(signal)) => format!("sig {signal}"),
+            _ => String::new(),
+        };
+        let trigger = match record.attempt {
+            1 => record.trigger,
+            attempt => format!("{} ({attempt})", record.trigger),
+        };
+        table.row(vec![
+            record.run.to_string(),
+            record.task,
+            trigger,
+            record.started.format("%Y-%m-%d %H:%M:%S").to_string(),
+            table::duration(record.duration_ms),
+            exit,
+            record.success.to_string(),
+        ]);
+    }
+    print!("{}", table.render());
+
+    Ok(())
+}
+
+/// Resolves a single task from the config.
+fn resolve(config: Config, id: &str) -> eyre::Result<ResolvedTask> {
+    let (_, mut tasks): (_, HashMap<String, ResolvedTask>) = config.try_into()?;
+    tasks
+        .remove(id)
+        .ok_or_else(|| eyre::eyre!("Unknown task `{id}`"))
+}
+
+/// The exit code to pass on for a task, using the shell convention for signals.
+fn exit_code(code: Option<i32>, signal: Option<i32>) -> i32 {
+    code.or(signal.map(|s| 128 + s)).unwrap_or(1)
+}
+
+fn init_events(format: EventFormat) {
+    let builder = tracing_subscriber::fmt().with_writer(std::io::stderr);
+    match format {
+        EventFormat::Text => builder.init(),
+        EventFormat::Json => builder.json().flatten_event(true).init(),
+    }
+}
+
+fn load_config(path: &Path) -> eyre::Result<Config> {
+    let contents = fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read config `{}`", path.display()))?;
+    contents
+        .parse()
+        .wrap_err_with(|| format!("Failed to parse config `{}`", path.display()))
+}
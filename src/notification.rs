@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod test;
+
+use std::{process::Stdio, thread, time::Duration};
+
+use chrono::{DateTime, Local};
+use color_eyre::eyre;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{
+    config::{Notify, NotifyAction, Outcome, ResolvedTask},
+    probe::{self, HttpUrl},
+};
+
+/// How long to wait for a notification URL to respond.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The details of an outcome of a task, which are sent with notifications.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Notification {
+    pub task: String,
+    pub outcome: Outcome,
+    pub time: DateTime<Local>,
+    /// The run that had the outcome, which is missing for a start that timed
+    /// out in the queue.
+    pub run: Option<u64>,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    /// The last lines of the run's output.
+    pub output_tail: Vec<String>,
+}
+
+impl Notification {
+    /// The environment variables that notification commands are run with.
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        let mut vars = vec![
+            ("SERVUM_TASK", self.task.clone()),
+            ("SERVUM_OUTCOME", self.outcome.as_str().to_owned()),
+            ("SERVUM_OUTPUT_TAIL", self.output_tail.join("\n")),
+        ];
+        if let Some(run) = self.run {
+            vars.push(("SERVUM_RUN_ID", run.to_string()));
+        }
+        if let Some(code) = self.exit_code {
+            vars.push(("SERVUM_EXIT_CODE", code.to_string()));
+        }
+        if let Some(signal) = self.signal {
+            vars.push(("SERVUM_SIGNAL", signal.to_string()));
+        }
+        vars
+    }
+}
+
+/// Sends a notification in the background, with `notify.failed` logged if it
+/// doesn't succeed.
+pub fn send(task: &ResolvedTask, notify: &Notify, notification: Notification) -> eyre::Result<()> {
+    match &notify.action {
+        NotifyAction::Cmd(cmd) => {
+            let mut child = task
+                .command(&task.argv_for(cmd)?)
+                .envs(notification.env())
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()?;
+            thread::spawn(move || match child.wait() {
+                Ok(status) if status.success() => {}
+                Ok(status) => failed(&notification, &format!("Command failed ({status})")),
+                Err(err) => failed(&notification, &err.to_string()),
+            });
+        }
+        NotifyAction::Url(url) => {
+            let url = HttpUrl::parse(url)?;
+            let body = serde_json::to_string(&notification)?;
+            thread::spawn(move || match probe::post_json(&url, &body, TIMEOUT) {
+                Ok(status) if (200..300).contains(&status) => {}
+                Ok(status) => failed(&notification, &format!("Got status {status}")),
+                Err(err) => failed(&notification, &format!("{err:#}")),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn failed(notification: &Notification, error: &str) {
+    let task = &notification.task;
+    warn!(
+        event = "notify.failed",
+        task,
+        outcome = notification.outcome.as_str(),
+        error,
+        "Failed to notify about task `{task}`: {error}"
+    );
+}
@@ -0,0 +1,46 @@
+use chrono::TimeZone;
+use pretty_assertions::assert_eq;
+
+use super::*;
+
+fn notification() -> Notification {
+    Notification {
+        task: "backup".to_owned(),
+        outcome: Outcome::CrashLoop,
+        time: Local.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap(),
+        run: Some(4),
+        exit_code: Some(2),
+        signal: None,
+        output_tail: vec!["copying".to_owned(), "disk full".to_owned()],
+    }
+}
+
+#[test]
+fn test_notification_env() {
+    assert_eq!(
+        notification().env(),
+        [
+            ("SERVUM_TASK", "backup".to_owned()),
+            ("SERVUM_OUTCOME", "crash-loop".to_owned()),
+            ("SERVUM_OUTPUT_TAIL", "copying\ndisk full".to_owned()),
+            ("SERVUM_RUN_ID", "4".to_owned()),
+            ("SERVUM_EXIT_CODE", "2".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn test_notification_json() {
+    let expected = serde_json::json!({
+        "task": "backup",
+        "outcome": "crash-loop",
+        "run": 4,
+        "exit_code": 2,
+        "signal": null,
+        "output_tail": ["copying", "disk full"],
+    });
+    let mut json = serde_json::to_value(notification()).unwrap();
+    assert!(json["time"].is_string());
+    json.as_object_mut().unwrap().remove("time");
+    assert_eq!(json, expected);
+}
@@ -0,0 +1,307 @@
+#[cfg(test)]
+mod test;
+
+use std::{
+    fs,
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::{self, WrapErr};
+use regex::Regex;
+
+use crate::{
+    config::{Check, Health, Ready, ResolvedTask},
+    log::{Output, Stream},
+    scheduler::Event,
+};
+
+/// A check that can be run repeatedly from another thread.
+pub enum Probe {
+    Tcp(String),
+    File(PathBuf),
+    Socket(PathBuf),
+    Cmd(Command),
+    Http(HttpUrl),
+    Heartbeat { file: PathBuf, max_age: Duration },
+}
+
+/// The parts of an `http://` URL needed to make a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpUrl {
+    /// The host and port, as used for the `Host` header.
+    pub host: String,
+    pub addr: String,
+    pub path: String,
+}
+
+impl HttpUrl {
+    pub fn parse(url: &str) -> eyre::Result<Self> {
+        if url.starts_with("https://") {
+            eyre::bail!(
+                "`https://` URLs such as `{url}` aren't supported, as servum can't use TLS; \
+                 use a `cmd` (e.g. with `curl`) instead"
+            );
+        }
+        let Some(rest) = url.strip_prefix("http://") else {
+            eyre::bail!("Only `http://` URLs are supported, not `{url}`");
+        };
+        let (host, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/"),
+        };
+        if host.is_empty() {
+            eyre::bail!("URL `{url}` has no host");
+        }
+
+        let has_port = host
+            .rsplit_once(':')
+            .is_some_and(|(_, port)| !port.contains(']'));
+        let addr = if has_port {
+            host.to_owned()
+        } else {
+            format!("{host}:80")
+        };
+        Ok(Self {
+            host: host.to_owned(),
+            addr,
+            path: path.to_owned(),
+        })
+    }
+}
+
+impl Probe {
+    /// Prepares a check for a task, or returns `None` if it can't be polled.
+    pub fn new(task: &ResolvedTask, check: &Check) -> eyre::Result<Option<Self>> {
+        Ok(Some(match check {
+            Check::Tcp(addr) => Self::Tcp(addr.clone()),
+            Check::File(path) => Self::File(path.clone()),
+            Check::Socket(path) => Self::Socket(path.clone()),
+            Check::Cmd(cmd) => {
+                let mut cmd = task.command(&task.argv_for(cmd)?);
+                cmd.stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null());
+                Self::Cmd(cmd)
+            }
+            Check::Http(url) => Self::Http(HttpUrl::parse(url)?),
+            Check::Heartbeat(heartbeat) => Self::Heartbeat {
+                file: heartbeat.file.clone(),
+                max_age: Duration::from_millis(heartbeat.max_age as u64),
+            },
+            Check::Stdout(_) => return Ok(None),
+        }))
+    }
+
+    /// Runs the check once, returning whether it passed within `timeout`.
+    pub fn run(&mut self, timeout: Duration) -> bool {
+        match self {
+            Self::Tcp(addr) => addr.to_socket_addrs().is_ok_and(|mut addrs| {
+                addrs.any(|addr| TcpStream::connect_timeout(&addr, timeout).is_ok())
+            }),
+            Self::File(path) => path.exists(),
+            Self::Socket(path) => UnixStream::connect(path).is_ok(),
+            Self::Cmd(cmd) => run_cmd(cmd, timeout),
+            Self::Http(url) => get(url, timeout).is_ok_and(|status| (200..400).contains(&status)),
+            Self::Heartbeat { file, max_age } => fs::metadata(file)
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| modified.elapsed().unwrap_or_default() <= *max_age),
+        }
+    }
+}
+
+/// Runs a command, killing it if it takes longer than `timeout`.
+fn run_cmd(cmd: &mut Command, timeout: Duration) -> bool {
+    let Ok(mut child) = cmd.spawn() else {
+        return false;
+    };
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return status.success(),
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return false;
+            }
+        }
+    }
+}
+
+/// Makes a `GET` request, returning the status code of the response.
+fn get(url: &HttpUrl, timeout: Duration) -> eyre::Result<u16> {
+    request(url, "GET", None, timeout)
+}
+
+/// Makes a `POST` request with a JSON body, returning the status code of the response.
+pub fn post_json(url: &HttpUrl, body: &str, timeout: Duration) -> eyre::Result<u16> {
+    request(url, "POST", Some(body), timeout)
+}
+
+fn request(
+    url: &HttpUrl,
+    method: &str,
+    body: Option<&str>,
+    timeout: Duration,
+) -> eyre::Result<u16> {
+    let mut last_err = None;
+    for addr in url.addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(mut stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                let headers = match body {
+                    Some(body) => format!(
+                        "Content-Type: application/json\r\nContent-Length: {}\r\n",
+                        body.len()
+                    ),
+                    None => String::new(),
+                };
+                let request = format!(
+                    "{method} {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n{headers}\r\n{}",
+                    url.path,
+                    url.host,
+                    body.unwrap_or_default()
+                );
+                stream.write_all(request.as_bytes())?;
+
+                // Only the status line is needed, e.g. `HTTP/1.1 200 OK`.
+                let mut head = [0; 32];
+                let mut len = 0;
+                while len < head.len() {
+                    match stream.read(&mut head[len..])? {
+                        0 => break,
+                        n => len += n,
+                    }
+                }
+                let head = String::from_utf8_lossy(&head[..len]);
+                let status = head
+                    .split_whitespace()
+                    .nth(1)
+                    .and_then(|s| s.parse().ok())
+                    .ok_or_else(|| eyre::eyre!("Invalid HTTP response"))?;
+                return Ok(status);
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    Err(last_err.map_or_else(|| eyre::eyre!("No address for `{}`", url.addr), Into::into))
+}
+
+/// Waits for a run of a task to become ready according to its `ready` check, sending
+/// [`Event::Ready`] once it is.
+///
+/// Checking stops once `cancel` is set.
+pub fn ready(
+    id: &str,
+    run: u64,
+    task: &ResolvedTask,
+    ready: &Ready,
+    output: &Output,
+    tx: Sender<Event>,
+    cancel: Arc<AtomicBool>,
+) -> eyre::Result<()> {
+    let interval = Duration::from_millis(ready.interval as u64);
+    let timeout = Duration::from_millis(ready.timeout as u64);
+    let check = &ready.check;
+    let task_id = id.to_owned();
+    let ready = move || {
+        let _ = tx.send(Event::Ready { task: task_id, run });
+    };
+
+    if let Check::Stdout(pattern) = check {
+        let regex = Regex::new(pattern).wrap_err("Invalid `ready.stdout` regex")?;
+        let matches =
+            move |stream: Stream, text: &str| stream == Stream::Stdout && regex.is_match(text);
+
+        let mut buffer = output.buffer.lock().unwrap_or_else(PoisonError::into_inner);
+        if buffer.lines(Some(run)).any(|l| matches(l.stream, &l.text)) {
+            ready();
+            return Ok(());
+        }
+
+        let mut ready = Some(ready);
+        buffer.follow(Some(run), move |line| {
+            if cancel.load(Ordering::Relaxed) {
+                return false;
+            }
+            if !matches(line.stream, &line.text) {
+                return true;
+            }
+            if let Some(ready) = ready.take() {
+                ready();
+            }
+            false
+        });
+        return Ok(());
+    }
+
+    let Some(mut probe) = Probe::new(task, check)? else {
+        return Ok(());
+    };
+    thread::spawn(move || {
+        while !cancel.load(Ordering::Relaxed) {
+            if probe.run(timeout) {
+                ready();
+                return;
+            }
+            thread::sleep(interval);
+        }
+    });
+
+    Ok(())
+}
+
+/// Runs the `health` check of a run of a task every interval, sending
+/// [`Event::Health`] with each result.
+///
+/// Checking stops once `cancel` is set.
+pub fn health(
+    id: &str,
+    run: u64,
+    task: &ResolvedTask,
+    health: &Health,
+    tx: Sender<Event>,
+    cancel: Arc<AtomicBool>,
+) -> eyre::Result<()> {
+    let Some(mut probe) = Probe::new(task, &health.check)? else {
+        eyre::bail!("`health` can't check stdout");
+    };
+    let interval = Duration::from_millis(health.interval as u64);
+    let timeout = Duration::from_millis(health.timeout as u64);
+    let task = id.to_owned();
+
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        let healthy = probe.run(timeout);
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        let event = Event::Health {
+            task: task.clone(),
+            run,
+            healthy,
+        };
+        if tx.send(event).is_err() {
+            return;
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use std::net::TcpListener;
+
+use pretty_assertions::assert_eq;
+
+use super::*;
+
+#[test]
+fn test_parse_http_url() {
+    assert_eq!(
+        HttpUrl::parse("http://localhost:8080/health?full=1").unwrap(),
+        HttpUrl {
+            host: "localhost:8080".to_owned(),
+            addr: "localhost:8080".to_owned(),
+            path: "/health?full=1".to_owned(),
+        }
+    );
+    assert_eq!(
+        HttpUrl::parse("http://[::1]").unwrap(),
+        HttpUrl {
+            host: "[::1]".to_owned(),
+            addr: "[::1]:80".to_owned(),
+            path: "/".to_owned(),
+        }
+    );
+    assert!(HttpUrl::parse("https://localhost").is_err());
+    assert!(HttpUrl::parse("http:///health").is_err());
+}
+
+#[test]
+fn test_probe_http() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/health", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        for status in ["200 OK", "503 Service Unavailable"] {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let len = stream.read(&mut request).unwrap();
+            assert!(request[..len].starts_with(b"GET /health HTTP/1.0\r\n"));
+            write!(stream, "HTTP/1.1 {status}\r\n\r\n").unwrap();
+        }
+    });
+
+    let mut probe = Probe::Http(HttpUrl::parse(&url).unwrap());
+    assert!(probe.run(Duration::from_secs(5)));
+    assert!(!probe.run(Duration::from_secs(5)));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_probe_heartbeat() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("heartbeat");
+    let mut probe = Probe::Heartbeat {
+        file: file.clone(),
+        max_age: Duration::from_mins(1),
+    };
+    assert!(!probe.run(Duration::from_secs(1)));
+
+    fs::write(&file, "").unwrap();
+    assert!(probe.run(Duration::from_secs(1)));
+
+    let mut probe = Probe::Heartbeat {
+        file,
+        max_age: Duration::ZERO,
+    };
+    thread::sleep(Duration::from_millis(10));
+    assert!(!probe.run(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_post_json() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = HttpUrl::parse(&format!("http://{}/hook", listener.local_addr().unwrap())).unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = vec![];
+        let mut buf = [0; 1024];
+        while !request.ends_with(b"{\"ok\":true}") {
+            let len = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..len]);
+        }
+        stream
+            .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+            .unwrap();
+        String::from_utf8(request).unwrap()
+    });
+
+    let status = post_json(&url, "{\"ok\":true}", Duration::from_secs(5)).unwrap();
+    assert_eq!(status, 204);
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /hook HTTP/1.0\r\n"));
+    assert!(request.contains("Content-Type: application/json\r\nContent-Length: 11\r\n\r\n"));
+}
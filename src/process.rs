@@ -0,0 +1,511 @@
+#[cfg(test)]
+mod test;
+
+use std::{
+    env,
+    ffi::OsString,
+    fmt,
+    fs::{File, OpenOptions},
+    hash::Hash,
+    io,
+    os::{fd::AsRawFd, unix::process::CommandExt},
+    path::Path,
+    process::{Command, Stdio},
+    rc::Rc,
+    sync::{atomic::AtomicBool, mpsc::Sender, Arc},
+    thread,
+};
+
+use color_eyre::eyre;
+use notify::Watcher;
+
+use crate::{
+    config::{MultiStr, Notify, PathApplyMethod, ResolvedTask},
+    explain::Traced,
+    log::{Output, Stream},
+    mail,
+    notification::{self, Notification},
+    probe,
+    scheduler::{Event, Trigger},
+    watch,
+};
+
+/// Starts and signals the processes of tasks on behalf of the scheduler.
+///
+/// Once a process exits, [`Event::Exited`] and then [`Event::OutputClosed`]
+/// must be sent for it.
+pub trait Processes {
+    /// Starts a run of a task, returning its pid and the argv that was run.
+    ///
+    /// The environment variables of `trigger` are added to the task's own.
+    fn spawn(
+        &mut self,
+        id: &str,
+        run: u64,
+        task: &ResolvedTask,
+        trigger: &Trigger,
+        output: &Output,
+        tx: Sender<Event>,
+    ) -> eyre::Result<(u32, Vec<String>)>;
+
+    /// Runs the `cmd-stop` of a task, sending [`Event::StopExited`] once it exits
+    /// if `wait` is set.
+    fn spawn_stop(
+        &mut self,
+        id: &str,
+        run: u64,
+        task: &ResolvedTask,
+        argv: &[String],
+        wait: bool,
+        tx: Sender<Event>,
+    ) -> io::Result<()>;
+
+    /// Starts checking whether a run of a task with a `ready` check is ready,
+    /// sending [`Event::Ready`] once it is.
+    ///
+    /// Checking stops once `cancel` is set.
+    fn ready(
+        &mut self,
+        id: &str,
+        run: u64,
+        task: &ResolvedTask,
+        output: &Output,
+        tx: Sender<Event>,
+        cancel: Arc<AtomicBool>,
+    ) -> eyre::Result<()>;
+
+    /// Starts running the `health` check of a run of a task, if it has one,
+    /// sending [`Event::Health`] with each result.
+    ///
+    /// Checking stops once `cancel` is set.
+    fn health(
+        &mut self,
+        id: &str,
+        run: u64,
+        task: &ResolvedTask,
+        tx: Sender<Event>,
+        cancel: Arc<AtomicBool>,
+    ) -> eyre::Result<()>;
+
+    /// Starts watching the `watch-paths` and `restart-on-change` of a task,
+    /// sending [`Event::Changed`] and [`Event::SourcesChanged`] when they change.
+    fn watch(
+        &mut self,
+        id: &str,
+        task: &ResolvedTask,
+        tx: Sender<Event>,
+    ) -> eyre::Result<Vec<Box<dyn Watcher>>>;
+
+    /// Sends a notification about a task in the background.
+    fn notify(
+        &mut self,
+        task: &ResolvedTask,
+        notify: &Notify,
+        notification: Notification,
+    ) -> eyre::Result<()>;
+
+    /// Pipes an email about a run of a task into `sendmail` in the background.
+    fn mail(&mut self, id: &str, run: u64, sendmail: Vec<String>, message: String);
+
+    /// Takes the `lock-file` at `path` without waiting, returning `None` if it is
+    /// already held, e.g. by another instance of servum.
+    fn lock(&mut self, path: &Path) -> io::Result<Option<FileLock>>;
+
+    fn signal(&mut self, pid: u32, signal: libc::c_int);
+}
+
+/// A held `lock-file`, which is released when dropped.
+pub struct FileLock {
+    _file: Option<File>,
+}
+
+impl FileLock {
+    /// A lock that isn't backed by a file, for when nothing is really run.
+    pub fn stub() -> Self {
+        Self { _file: None }
+    }
+}
+
+/// Real processes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct System {
+    /// Whether task watchers should poll, according to `watch.force-poll`.
+    pub force_poll: bool,
+}
+
+impl Processes for System {
+    fn spawn(
+        &mut self,
+        id: &str,
+        run: u64,
+        task: &ResolvedTask,
+        trigger: &Trigger,
+        output: &Output,
+        tx: Sender<Event>,
+    ) -> eyre::Result<(u32, Vec<String>)> {
+        let argv = task.argv()?;
+        let mut cmd = task.command(&argv);
+        cmd.envs(trigger.env())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let mut child = cmd.spawn()?;
+        let pid = child.id();
+
+        let mut pipes = vec![];
+        if let Some(stdout) = child.stdout.take() {
+            pipes.push(output.pipe(id, run, Stream::Stdout, stdout));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            pipes.push(output.pipe(id, run, Stream::Stderr, stderr));
+        }
+
+        let task = id.to_owned();
+        thread::spawn(move || {
+            let status = child.wait();
+            // The scheduler only goes away when the process is exiting.
+            let _ = tx.send(Event::Exited {
+                task: task.clone(),
+                run,
+                status,
+            });
+
+            // Anything the process left running in the background can keep its output
+            // open, so this is reported separately to avoid holding up the exit.
+            for pipe in pipes {
+                let _ = pipe.join();
+            }
+            let _ = tx.send(Event::OutputClosed { task, run });
+        });
+
+        Ok((pid, argv))
+    }
+
+    fn spawn_stop(
+        &mut self,
+        id: &str,
+        run: u64,
+        task: &ResolvedTask,
+        argv: &[String],
+        wait: bool,
+        tx: Sender<Event>,
+    ) -> io::Result<()> {
+        let mut child = task
+            .command(argv)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let task = id.to_owned();
+        thread::spawn(move || {
+            let status = child.wait();
+            if wait {
+                let _ = tx.send(Event::StopExited { task, run, status });
+            }
+        });
+
+        Ok(())
+    }
+
+    fn ready(
+        &mut self,
+        id: &str,
+        run: u64,
+        task: &ResolvedTask,
+        output: &Output,
+        tx: Sender<Event>,
+        cancel: Arc<AtomicBool>,
+    ) -> eyre::Result<()> {
+        let Some(ready) = &task.config.ready else {
+            return Ok(());
+        };
+        probe::ready(id, run, task, ready, output, tx, cancel)
+    }
+
+    fn health(
+        &mut self,
+        id: &str,
+        run: u64,
+        task: &ResolvedTask,
+        tx: Sender<Event>,
+        cancel: Arc<AtomicBool>,
+    ) -> eyre::Result<()> {
+        let Some(health) = &task.config.health else {
+            return Ok(());
+        };
+        probe::health(id, run, task, health, tx, cancel)
+    }
+
+    fn watch(
+        &mut self,
+        id: &str,
+        task: &ResolvedTask,
+        tx: Sender<Event>,
+    ) -> eyre::Result<Vec<Box<dyn Watcher>>> {
+        watch::task(id, &task.config, self.force_poll, &tx)
+    }
+
+    fn notify(
+        &mut self,
+        task: &ResolvedTask,
+        notify: &Notify,
+        notification: Notification,
+    ) -> eyre::Result<()> {
+        notification::send(task, notify, notification)
+    }
+
+    fn mail(&mut self, id: &str, run: u64, sendmail: Vec<String>, message: String) {
+        mail::send(id, run, sendmail, message);
+    }
+
+    fn lock(&mut self, path: &Path) -> io::Result<Option<FileLock>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        // SAFETY: `flock` has no memory safety requirements.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+            return Ok(Some(FileLock { _file: Some(file) }));
+        }
+
+        let err = io::Error::last_os_error();
+        match err.kind() {
+            io::ErrorKind::WouldBlock => Ok(None),
+            _ => Err(err),
+        }
+    }
+
+    fn signal(&mut self, pid: u32, signal: libc::c_int) {
+        let Ok(pid) = libc::pid_t::try_from(pid) else {
+            return;
+        };
+        // SAFETY: `kill` has no memory safety requirements.
+        unsafe {
+            libc::kill(pid, signal);
+        }
+    }
+}
+
+impl ResolvedTask {
+    /// The full argv that will be executed for this task.
+    ///
+    /// If a shell is set and is a single value, then the command is passed to it
+    /// using `-c`. If multiple values are given, then the command is appended as
+    /// the final argument, which allows using shells that take different flags.
+    ///
+    /// Without a shell, a single string command is split on whitespace.
+    pub fn argv(&self) -> eyre::Result<Vec<String>> {
+        let Some(cmd) = &self.config.cmd else {
+            eyre::bail!("Task has no `cmd` to run");
+        };
+
+        self.argv_for(cmd)
+    }
+
+    /// The argv used to stop this task, if it has a `cmd-stop`.
+    pub fn stop_argv(&self) -> Option<eyre::Result<Vec<String>>> {
+        self.config.cmd_stop.as_ref().map(|cmd| self.argv_for(cmd))
+    }
+
+    /// The argv to run `cmd` with, using this task's shell.
+    pub fn argv_for(&self, cmd: &MultiStr) -> eyre::Result<Vec<String>> {
+        let argv: Vec<String> = match (&self.shell, cmd) {
+            (Some(shell), cmd) => {
+                let cmd = match cmd {
+                    MultiStr::Single(cmd) => cmd.clone(),
+                    MultiStr::Multi(cmd) => cmd.join(" "),
+                };
+                let mut argv: Vec<_> = shell.iter().map(|s| String::clone(s)).collect();
+                if argv.len() == 1 {
+                    argv.push("-c".to_owned());
+                }
+                argv.push(cmd);
+                argv
+            }
+            (None, MultiStr::Single(cmd)) => {
+                cmd.split_whitespace().map(ToOwned::to_owned).collect()
+            }
+            (None, MultiStr::Multi(cmd)) => cmd.clone(),
+        };
+
+        if argv.is_empty() || argv[0].is_empty() {
+            eyre::bail!("Command is empty");
+        }
+
+        Ok(argv)
+    }
+
+    /// Computes the final environment that the task will run with, given the
+    /// environment of the parent process.
+    pub fn environment(
+        &self,
+        parent: impl IntoIterator<Item = (OsString, OsString)>,
+    ) -> Vec<(OsString, OsString)> {
+        // Where each variable came from is dropped, so which task set it doesn't matter.
+        self.build_environment(parent, |_| EnvSource::Task(String::new()))
+            .into_iter()
+            .map(|v| (v.key, v.value))
+            .collect()
+    }
+
+    /// Builds a command that runs the given argv with this task's environment.
+    pub fn command(&self, argv: &[String]) -> Command {
+        let mut cmd = Command::new(&argv[0]);
+        cmd.args(&argv[1..])
+            .env_clear()
+            .envs(self.environment(env::vars_os()));
+
+        // SAFETY: `signal` is async-signal-safe.
+        unsafe {
+            cmd.pre_exec(|| {
+                // Ignored signals are inherited, and servum is often started somewhere
+                // that ignores SIGINT, which would stop tasks from being stopped gracefully.
+                for sig in [libc::SIGINT, libc::SIGQUIT, libc::SIGTERM, libc::SIGHUP] {
+                    libc::signal(sig, libc::SIG_DFL);
+                }
+                Ok(())
+            });
+        }
+
+        cmd
+    }
+}
+
+impl ResolvedTask<Traced> {
+    /// The same as [`environment`](ResolvedTask::environment), but also records
+    /// where each variable came from.
+    pub fn annotated_environment(
+        &self,
+        parent: impl IntoIterator<Item = (OsString, OsString)>,
+    ) -> Vec<EnvVar> {
+        self.build_environment(parent, |v| EnvSource::Task(v.origin.task.clone()))
+    }
+}
+
+impl<S: Setting> ResolvedTask<S> {
+    /// Builds the environment of the task, with `source` giving where each of the
+    /// task's own variables came from.
+    fn build_environment(
+        &self,
+        parent: impl IntoIterator<Item = (OsString, OsString)>,
+        source: impl Fn(&S) -> EnvSource,
+    ) -> Vec<EnvVar> {
+        let mut vars: Vec<EnvVar> = match &self.env {
+            Some(e) if !e.merge => vec![],
+            _ => parent
+                .into_iter()
+                .map(|(key, value)| EnvVar {
+                    key,
+                    value,
+                    source: EnvSource::Parent,
+                })
+                .collect(),
+        };
+
+        if let Some(e) = &self.env {
+            let mut own: Vec<_> = e.vars.iter().collect();
+            own.sort_by_key(|(k, _)| k.as_str());
+            for (k, v) in own {
+                set_var(&mut vars, k.as_str().into(), v.as_str().into(), source(v));
+            }
+        }
+
+        if let Some(path) = &self.path {
+            let current = vars
+                .iter()
+                .find(|v| v.key == "PATH")
+                .map(|v| env::split_paths(&v.value).collect::<Vec<_>>())
+                .unwrap_or_default();
+            let dirs = path.dirs.iter().map(|d| d.as_str().into());
+
+            let joined = match path.apply {
+                PathApplyMethod::Before => env::join_paths(dirs.chain(current)),
+                PathApplyMethod::After => env::join_paths(current.into_iter().chain(dirs)),
+                PathApplyMethod::Overwrite => env::join_paths(dirs),
+            };
+
+            // Dirs containing the separator can't be represented, so the PATH is left alone.
+            if let Ok(joined) = joined {
+                set_var(&mut vars, "PATH".into(), joined, EnvSource::Path);
+            }
+        }
+
+        vars
+    }
+}
+
+/// A string of a resolved task.
+pub trait Setting: Hash + Eq {
+    fn as_str(&self) -> &str;
+}
+
+impl Setting for Rc<String> {
+    fn as_str(&self) -> &str {
+        self
+    }
+}
+
+impl Setting for Traced {
+    fn as_str(&self) -> &str {
+        &self.value
+    }
+}
+
+/// A single variable of a task's environment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvVar {
+    pub key: OsString,
+    pub value: OsString,
+    pub source: EnvSource,
+}
+
+/// Where the value of an environment variable came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvSource {
+    /// Passed through from servum's own environment.
+    Parent,
+    /// Set by the `env` of the task with the given id, which is either this task
+    /// or one that it extends.
+    Task(String),
+    /// Built from the task's `path`.
+    Path,
+}
+
+impl EnvSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Parent => "parent",
+            Self::Task(_) => "task",
+            Self::Path => "path",
+        }
+    }
+
+    /// The id of the task that set the variable, if it was set by one.
+    pub fn task(&self) -> Option<&str> {
+        match self {
+            Self::Task(task) => Some(task),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for EnvSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Task(task) => write!(f, "task:{task}"),
+            _ => f.write_str(self.as_str()),
+        }
+    }
+}
+
+fn set_var(vars: &mut Vec<EnvVar>, key: OsString, value: OsString, source: EnvSource) {
+    match vars.iter_mut().find(|v| v.key == key) {
+        Some(v) => {
+            v.value = value;
+            v.source = source;
+        }
+        None => vars.push(EnvVar { key, value, source }),
+    }
+}
@@ -0,0 +1,80 @@
+use map_macro::hashbrown::hash_map;
+use pretty_assertions::assert_eq;
+
+use super::*;
+use crate::{
+    config::{Env, Path, TaskConfig},
+    explain::Origin,
+};
+
+fn traced(task: &str, value: &str) -> Traced {
+    Traced {
+        value: value.to_owned(),
+        origin: Origin {
+            task: task.to_owned(),
+            line: None,
+        },
+    }
+}
+
+#[test]
+fn test_annotated_environment() {
+    let task = ResolvedTask {
+        config: TaskConfig::default(),
+        shell: None,
+        path: Some(Path {
+            dirs: vec![traced("web", "/opt/bin")],
+            apply: PathApplyMethod::Before,
+        }),
+        env: Some(Env {
+            vars: hash_map! {
+                traced("base", "HOME") => traced("base", "/srv"),
+            },
+            merge: true,
+        }),
+        log: None,
+    };
+    let parent =
+        [("HOME", "/root"), ("PATH", "/bin"), ("TERM", "xterm")].map(|(k, v)| (k.into(), v.into()));
+
+    let vars: Vec<_> = task
+        .annotated_environment(parent)
+        .into_iter()
+        .map(|v| {
+            (
+                v.key.into_string().unwrap(),
+                v.value.into_string().unwrap(),
+                v.source.to_string(),
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        vars,
+        [
+            ("HOME".to_owned(), "/srv".to_owned(), "task:base".to_owned()),
+            (
+                "PATH".to_owned(),
+                "/opt/bin:/bin".to_owned(),
+                "path".to_owned()
+            ),
+            ("TERM".to_owned(), "xterm".to_owned(), "parent".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn test_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("db.lock");
+
+    let mut system = System::default();
+
+    let lock = system.lock(&path).unwrap();
+    assert!(lock.is_some());
+    assert!(system.lock(&path).unwrap().is_none());
+
+    drop(lock);
+    assert!(system.lock(&path).unwrap().is_some());
+    assert!(system.lock(&dir.path().join("missing/db.lock")).is_err());
+}
@@ -0,0 +1,2419 @@
+use std::{
+    env, io, mem,
+    os::unix::process::ExitStatusExt,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, PoisonError,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use color_eyre::eyre::{self, WrapErr};
+use cron::Schedule;
+use hashbrown::HashMap;
+use notify::Watcher;
+use regex::Regex;
+use tracing::{info, warn};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    config::{
+        self, Check, Concurrency, Config, FsEvent, NotifyAction, Outcome, RequirementFailure,
+        ResolvedTask, Watch,
+    },
+    control::{Command, LastRun, Response, TaskState, TaskStatus},
+    history::{self, Store},
+    log::{Foreground, Line, Output},
+    mail,
+    notification::Notification,
+    probe::HttpUrl,
+    process::{FileLock, Processes, System},
+    state::{State, TaskState as SavedTask},
+    table,
+    watch::Matcher,
+};
+
+/// Messages sent to the scheduler loop from other threads.
+#[derive(Debug)]
+pub enum Event {
+    /// A task's process has exited.
+    Exited {
+        task: String,
+        run: u64,
+        status: io::Result<ExitStatus>,
+    },
+    /// The `cmd-stop` of a task has exited.
+    StopExited {
+        task: String,
+        run: u64,
+        status: io::Result<ExitStatus>,
+    },
+    /// Every output stream of a task's process has closed.
+    ///
+    /// This is always sent after [`Event::Exited`] for the same run.
+    OutputClosed { task: String, run: u64 },
+    /// A run of a task has passed its `ready` check.
+    Ready { task: String, run: u64 },
+    /// The result of a `health` check of a run of a task.
+    Health {
+        task: String,
+        run: u64,
+        healthy: bool,
+    },
+    /// Paths in a task's `watch-paths` have changed.
+    Changed { task: String, paths: Vec<PathBuf> },
+    /// Paths in a task's `restart-on-change` have changed.
+    SourcesChanged { task: String, paths: Vec<PathBuf> },
+    /// The config file has changed.
+    Reload,
+    /// A request from the control socket.
+    Control {
+        command: Command,
+        reply: Sender<Response>,
+    },
+}
+
+/// Why a task was started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    /// The task's `cron` was due at `scheduled`.
+    Cron {
+        scheduled: DateTime<Local>,
+    },
+    OnStart,
+    Manual,
+    RunOnce,
+    /// The task's `health` check failed too many times in a row, so it is
+    /// being restarted.
+    Health,
+    /// Another task has finished, and lists this one in its `on-success`,
+    /// `on-failure` or `on-complete`.
+    Finished {
+        on: Finish,
+        task: String,
+        last: LastRun,
+    },
+    /// Paths in the task's `watch-paths` have changed.
+    Watch {
+        paths: Vec<PathBuf>,
+    },
+    /// Paths in the task's `restart-on-change` have changed.
+    Change {
+        paths: Vec<PathBuf>,
+    },
+    /// A run started by `trigger` failed, and this is the given attempt at it.
+    Retry {
+        trigger: Box<Trigger>,
+        attempt: u32,
+    },
+}
+
+/// Which list of a finished task started another task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Finish {
+    Success,
+    Failure,
+    Complete,
+}
+
+impl Trigger {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Cron { .. } => "cron",
+            Self::OnStart => "on-start",
+            Self::Manual => "manual",
+            Self::RunOnce => "run-once",
+            Self::Health => "health",
+            Self::Finished { on, .. } => match on {
+                Finish::Success => "on-success",
+                Finish::Failure => "on-failure",
+                Finish::Complete => "on-complete",
+            },
+            Self::Watch { .. } => "watch",
+            Self::Change { .. } => "change",
+            Self::Retry { .. } => "retry",
+        }
+    }
+
+    /// Which attempt at running the task this is, starting from `1`.
+    fn attempt(&self) -> u32 {
+        match self {
+            Self::Retry { attempt, .. } => *attempt,
+            _ => 1,
+        }
+    }
+
+    /// When the run was due, if it was started by `cron`.
+    fn scheduled(&self) -> Option<DateTime<Local>> {
+        match self {
+            Self::Cron { scheduled } => Some(*scheduled),
+            Self::Retry { trigger, .. } => trigger.scheduled(),
+            _ => None,
+        }
+    }
+
+    /// The environment variables that tell a task why it was started.
+    ///
+    /// Retries are given the variables of the original start, along with
+    /// `SERVUM_ATTEMPT`.
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        if let Self::Retry { trigger, attempt } = self {
+            let mut vars = trigger.env();
+            vars.push(("SERVUM_ATTEMPT", attempt.to_string()));
+            return vars;
+        }
+
+        let mut vars = vec![("SERVUM_TRIGGER", self.as_str().to_owned())];
+        if let Self::Finished { task, last, .. } = self {
+            vars.push(("SERVUM_TRIGGER_TASK", task.clone()));
+            vars.push(("SERVUM_TRIGGER_RUN", last.run.to_string()));
+            vars.push(("SERVUM_TRIGGER_SUCCESS", last.success.to_string()));
+            if let Some(code) = last.exit_code {
+                vars.push(("SERVUM_TRIGGER_EXIT_CODE", code.to_string()));
+            }
+            if let Some(signal) = last.signal {
+                vars.push(("SERVUM_TRIGGER_SIGNAL", signal.to_string()));
+            }
+        }
+        if let Self::Watch { paths } | Self::Change { paths } = self {
+            let paths: Vec<_> = paths.iter().map(|p| p.to_string_lossy()).collect();
+            vars.push(("SERVUM_CHANGED_PATHS", paths.join("\n")));
+        }
+        vars
+    }
+
+    /// The task and run that caused this one, if any.
+    fn source(&self) -> Option<(&str, u64)> {
+        match self {
+            Self::Finished { task, last, .. } => Some((task, last.run)),
+            Self::Retry { trigger, .. } => trigger.source(),
+            _ => None,
+        }
+    }
+}
+
+/// How many lines of output are sent with notifications.
+const OUTPUT_TAIL: usize = 20;
+
+/// How long to wait for the output of a run to close once its process has
+/// exited, as a process left running in the background may be holding it open.
+const OUTPUT_GRACE: Duration = Duration::from_secs(5);
+
+pub struct Scheduler {
+    /// The path of the config file, used for reloading.
+    config: PathBuf,
+    entries: HashMap<String, Entry>,
+    /// Every task id, ordered so that tasks come after their dependencies.
+    order: Vec<String>,
+    next_run: u64,
+    /// Set once a shutdown has been requested, which finishes once every task has stopped.
+    shutting_down: bool,
+    tx: Sender<Event>,
+    rx: Receiver<Event>,
+    clock: Box<dyn Clock>,
+    processes: Box<dyn Processes>,
+    /// Set when running in the foreground, to label the output of each task.
+    foreground: Option<Foreground>,
+    /// Whether to shut down if an `on-start` task fails.
+    strict: bool,
+    concurrency: Concurrency,
+    /// The `on-start` task whose failure caused a shutdown, when `strict` is set.
+    failed: Option<String>,
+    state: State,
+    /// Where `state` is saved, if it is being persisted.
+    state_file: Option<PathBuf>,
+    /// Where finished runs are recorded, if they are.
+    history: Option<Store>,
+}
+
+struct Entry {
+    task: ResolvedTask,
+    schedule: Option<Schedule>,
+    next: Option<DateTime<Local>>,
+    output: Output,
+    running: Option<Run>,
+    /// The task has been removed from the config, and only exists until its
+    /// process has stopped.
+    removed: bool,
+    /// Start the task again once the current process has stopped.
+    restart: Option<Trigger>,
+    restarts: u32,
+    last: Option<LastRun>,
+    /// Requests waiting for a run to finish, sent its result once its output has closed.
+    waiters: Vec<(u64, Sender<Response>)>,
+    /// Start the task once its requirements are ready.
+    waiting: Option<Trigger>,
+    /// Start the task once a concurrency limit allows it.
+    queued: Option<Queued>,
+    /// Retry a failed run at the given time.
+    retry: Option<(DateTime<Local>, Trigger)>,
+    /// How many runs in a row have failed, not counting those that were stopped.
+    failed_runs: u32,
+    /// When each of the task's notifications was last sent, for rate limiting.
+    notified: HashMap<usize, DateTime<Local>>,
+    /// Set once the task's `expect-success-within` has lapsed, until it next
+    /// succeeds.
+    overdue: bool,
+    /// Finished runs waiting for their output to close before being recorded in
+    /// the history, so that their output tail is complete.
+    unrecorded: Vec<history::Record>,
+    /// Watches the task's `watch-paths` and `restart-on-change`.
+    watchers: Vec<Box<dyn Watcher>>,
+    fail_on_output: Option<Regex>,
+    succeed_on_output: Option<Regex>,
+}
+
+struct Queued {
+    trigger: Trigger,
+    since: DateTime<Local>,
+    /// When to give up on starting the task, according to its `queue-timeout`.
+    expires: Option<DateTime<Local>>,
+}
+
+struct Run {
+    id: u64,
+    pid: u32,
+    trigger: Trigger,
+    started: DateTime<Local>,
+    /// Whether a stop has been requested.
+    stopping: bool,
+    /// When the process should be killed if it hasn't stopped by itself.
+    kill_at: Option<DateTime<Local>>,
+    /// Whether the `ready` check has passed.
+    ready: bool,
+    /// When to give up on the `ready` check.
+    ready_by: Option<DateTime<Local>>,
+    /// How many `health` checks in a row have failed.
+    failures: u32,
+    /// Set once the run has finished, to stop any checks on it.
+    cancel: Arc<AtomicBool>,
+    /// The task's `lock-file`, held until the run is dropped.
+    _lock_file: Option<FileLock>,
+    /// What the output has matched, if the task has `fail-on-output` or
+    /// `succeed-on-output`.
+    matched: Option<Arc<OutputMatched>>,
+    /// The exit status of the process, if it has exited but the run is waiting
+    /// for its output to close.
+    status: Option<io::Result<ExitStatus>>,
+    /// When to stop waiting for the output to close and finish anyway.
+    output_by: Option<DateTime<Local>>,
+}
+
+/// Which of a task's output regexes a run has matched.
+#[derive(Default)]
+struct OutputMatched {
+    fail: AtomicBool,
+    succeed: AtomicBool,
+}
+
+impl Scheduler {
+    pub fn new(
+        config: PathBuf,
+        tasks: HashMap<String, ResolvedTask>,
+        concurrency: Concurrency,
+        watch: &Watch,
+    ) -> eyre::Result<Self> {
+        Self::with_runtime(
+            config,
+            tasks,
+            concurrency,
+            Box::new(SystemClock),
+            Box::new(System {
+                force_poll: watch.force_poll,
+            }),
+        )
+    }
+
+    /// Creates a scheduler that gets the time from `clock` and runs tasks using `processes`.
+    pub fn with_runtime(
+        config: PathBuf,
+        tasks: HashMap<String, ResolvedTask>,
+        concurrency: Concurrency,
+        clock: Box<dyn Clock>,
+        processes: Box<dyn Processes>,
+    ) -> eyre::Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let now = clock.now();
+        let order = config::dependency_order(&tasks)?;
+
+        let entries = tasks
+            .into_iter()
+            .map(|(id, task)| {
+                let entry = Entry::new(&id, task, now)?;
+                if entry.task.config.enabled {
+                    scheduled(&id, entry.next);
+                }
+                Ok((id, entry))
+            })
+            .collect::<eyre::Result<_>>()?;
+
+        let mut scheduler = Self {
+            config,
+            entries,
+            order,
+            next_run: 1,
+            shutting_down: false,
+            tx,
+            rx,
+            clock,
+            processes,
+            foreground: None,
+            strict: false,
+            concurrency,
+            failed: None,
+            state: State::default(),
+            state_file: None,
+            history: None,
+        };
+        let ids: Vec<_> = scheduler.entries.keys().cloned().collect();
+        for id in ids {
+            scheduler.watch(&id);
+        }
+        scheduler.track(now);
+        Ok(scheduler)
+    }
+
+    /// Loads the state kept between restarts from `path`, and saves it there
+    /// whenever it changes.
+    pub fn persist(&mut self, path: PathBuf) -> eyre::Result<()> {
+        self.state = State::load(&path)?;
+        self.state_file = Some(path);
+        self.track(self.clock.now());
+        self.save_state();
+        Ok(())
+    }
+
+    /// Records every finished run in `history`. Run ids carry on from the last
+    /// one recorded, so that they stay unique in it across restarts.
+    pub fn history(&mut self, history: Store) {
+        self.next_run = self.next_run.max(history.last_run() + 1);
+        self.history = Some(history);
+    }
+
+    /// Labels the output of every task that goes to servum's own stdout/stderr,
+    /// for when it is running in the foreground.
+    pub fn foreground(&mut self, foreground: Foreground) {
+        for (id, entry) in &mut self.entries {
+            entry.output.label(&foreground.label(id));
+        }
+        self.foreground = Some(foreground);
+    }
+
+    /// Makes the scheduler shut down (and [`run`](Self::run) fail) if the
+    /// startup run of an `on-start` task fails to spawn, fails to become ready,
+    /// or exits unsuccessfully without being asked to stop.
+    pub fn strict(&mut self) {
+        self.strict = true;
+    }
+
+    /// A sender that can be used to pass events to the scheduler from other threads.
+    pub fn sender(&self) -> Sender<Event> {
+        self.tx.clone()
+    }
+
+    /// Runs the scheduler until it is asked to shut down.
+    pub fn run(mut self) -> eyre::Result<()> {
+        self.start_on_start();
+
+        loop {
+            let now = self.clock.now();
+            self.tick(now);
+
+            if self.shutting_down && self.entries.values().all(|e| e.running.is_none()) {
+                let ids: Vec<_> = self.entries.keys().cloned().collect();
+                for id in ids {
+                    self.record(&id, None);
+                }
+                info!(event = "shutdown", "Shut down");
+                return match self.failed {
+                    Some(task) => Err(eyre::eyre!("On-start task `{task}` failed")),
+                    None => Ok(()),
+                };
+            }
+
+            let event = match self.next_deadline() {
+                Some(next) => {
+                    match self
+                        .rx
+                        .recv_timeout((next - now).to_std().unwrap_or_default())
+                    {
+                        Ok(event) => event,
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => {
+                            unreachable!("scheduler holds a sender")
+                        }
+                    }
+                }
+                None => self.rx.recv().expect("scheduler holds a sender"),
+            };
+
+            self.handle(event);
+        }
+    }
+
+    /// Starts every enabled `on-start` task.
+    pub fn start_on_start(&mut self) {
+        let on_start: Vec<_> = self
+            .order
+            .iter()
+            .filter(|id| {
+                self.entries
+                    .get(*id)
+                    .is_some_and(|e| e.task.config.enabled && e.task.config.on_start)
+            })
+            .cloned()
+            .collect();
+        for id in on_start {
+            // A strict failure may have already started shutting down.
+            if self.shutting_down {
+                break;
+            }
+            self.start(&id, Trigger::OnStart);
+        }
+    }
+
+    /// Shuts down because the startup run of an `on-start` task failed, if
+    /// `strict` is set.
+    fn fail_strict(&mut self, id: &str) {
+        if !self.strict || self.shutting_down {
+            return;
+        }
+        warn!(
+            event = "shutdown.strict",
+            task = id,
+            "On-start task `{id}` failed, shutting down"
+        );
+        self.failed = Some(id.to_owned());
+        self.shutdown();
+    }
+
+    /// Does everything that is due at `now`.
+    pub fn tick(&mut self, now: DateTime<Local>) {
+        self.kill_overdue(now);
+        self.ready_overdue(now);
+        self.close_overdue_output(now);
+        self.expire_queued(now);
+        self.fire_due(now);
+        self.retry_due(now);
+        self.check_overdue(now);
+    }
+
+    /// Handles every event that has already been sent, without waiting for more.
+    pub fn handle_pending(&mut self) {
+        while let Ok(event) = self.rx.try_recv() {
+            self.handle(event);
+        }
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Exited { task, run, status } => self.exited(&task, run, status),
+            Event::StopExited { task, run, status } => {
+                if !status.as_ref().is_ok_and(ExitStatus::success) {
+                    self.kill(&task, run, "cmd-stop-failed");
+                }
+            }
+            Event::OutputClosed { task, run } => self.output_closed(&task, run),
+            Event::Ready { task, run } => self.ready(&task, run),
+            Event::Health { task, run, healthy } => self.health(&task, run, healthy),
+            Event::Changed { task, paths } => self.changed(&task, paths),
+            Event::SourcesChanged { task, paths } => self.sources_changed(&task, paths),
+            Event::Reload => {
+                // Failures are already logged.
+                let _ = self.reload();
+            }
+            Event::Control { command, reply } => self.control(command, &reply),
+        }
+    }
+
+    fn exited(&mut self, id: &str, run: u64, status: io::Result<ExitStatus>) {
+        let Some(entry) = self.entries.get_mut(id) else {
+            return;
+        };
+        let config = &entry.task.config;
+        let sends_output =
+            !config.notify.is_empty() || config.mail_to.as_ref().is_some_and(|to| !to.is_empty());
+        let Some(current) = entry.running.as_mut().filter(|r| r.id == run) else {
+            return;
+        };
+        current.cancel.store(true, Ordering::Relaxed);
+        // There's nothing left to kill if it takes a while to finish.
+        current.kill_at = None;
+
+        // Output regexes can only be checked, and the output sent with
+        // notifications and emails, once every line has been read.
+        if current.matched.is_some() || sends_output {
+            current.status = Some(status);
+            current.output_by = Some(self.clock.now() + OUTPUT_GRACE);
+            return;
+        }
+        self.finish(id, run, status);
+    }
+
+    /// Records the result of a run that has exited, and acts on it.
+    fn finish(&mut self, id: &str, run: u64, status: io::Result<ExitStatus>) {
+        let Some(entry) = self.entries.get_mut(id) else {
+            return;
+        };
+        let Some(current) = entry.running.take_if(|r| r.id == run) else {
+            return;
+        };
+        let finished = self.clock.now();
+        let duration_ms =
+            u64::try_from((finished - current.started).num_milliseconds()).unwrap_or(0);
+
+        let success = status.as_ref().is_ok_and(|status| {
+            let output_ok = current.matched.as_ref().is_none_or(|m| {
+                !m.fail.load(Ordering::Relaxed)
+                    && (entry.succeed_on_output.is_none() || m.succeed.load(Ordering::Relaxed))
+            });
+            output_ok
+                && status
+                    .code()
+                    .is_some_and(|c| entry.task.config.success_code(c))
+        });
+        entry.last = Some(LastRun {
+            run,
+            success,
+            exit_code: status.as_ref().ok().and_then(ExitStatus::code),
+            signal: status.as_ref().ok().and_then(ExitStatusExt::signal),
+            duration_ms,
+            finished,
+        });
+
+        match status {
+            Ok(status) => info!(
+                event = "task.finished",
+                task = id,
+                run,
+                pid = current.pid,
+                success,
+                exit_code = status.code(),
+                signal = status.signal(),
+                duration_ms,
+                "Task `{id}` finished ({status})"
+            ),
+            Err(err) => warn!(
+                event = "task.finished",
+                task = id,
+                run,
+                pid = current.pid,
+                success = false,
+                duration_ms,
+                error = %err,
+                "Failed to wait on task `{id}`: {err}"
+            ),
+        }
+
+        let success = entry.last.as_ref().is_some_and(|l| l.success);
+        if let Some(last) = entry.last.as_ref().filter(|_| self.history.is_some()) {
+            entry.unrecorded.push(history_record(id, &current, last));
+        }
+        let strict_failure =
+            !success && !current.stopping && matches!(current.trigger, Trigger::OnStart);
+        if entry.removed {
+            self.record(id, None);
+            self.entries.remove(id);
+        } else if let Some(trigger) = entry.restart.take() {
+            if !self.shutting_down {
+                entry.restarts += 1;
+                self.start(id, trigger);
+            }
+        } else if !success && !current.stopping && !self.shutting_down {
+            self.schedule_retry(id, run, current.trigger, finished);
+        }
+
+        if strict_failure {
+            self.fail_strict(id);
+        }
+        if success {
+            self.succeeded(id, finished);
+        }
+        if !current.stopping {
+            self.notify_finished(id, run, success);
+        }
+        self.mail(id, run);
+        if !success {
+            self.requirement_failed(id);
+        }
+        // Queued tasks get the freed slot before anything this run triggers, so
+        // that they go in order of priority.
+        self.start_queued();
+        if self.shutting_down {
+            self.stop_for_shutdown();
+        } else if let Some(last) = self.entries.get(id).and_then(|e| e.last.clone()) {
+            self.start_triggered(id, &last);
+        }
+        self.start_waiting();
+    }
+
+    /// Records when a task last succeeded, for its `expect-success-within`.
+    fn succeeded(&mut self, id: &str, finished: DateTime<Local>) {
+        let Some(saved) = self.state.tasks.get_mut(id) else {
+            return;
+        };
+        saved.last_success = Some(finished);
+        if let Some(entry) = self.entries.get_mut(id) {
+            entry.overdue = false;
+        }
+        self.save_state();
+    }
+
+    /// Sends the notifications for a run that finished by itself.
+    fn notify_finished(&mut self, id: &str, run: u64, success: bool) {
+        let Some(entry) = self.entries.get_mut(id) else {
+            return;
+        };
+        if success {
+            let recovered = entry.failed_runs > 0;
+            entry.failed_runs = 0;
+            if recovered {
+                self.notify(id, Outcome::Recovery, Some(run));
+            }
+            return;
+        }
+
+        entry.failed_runs += 1;
+        self.notify(id, Outcome::Failure, Some(run));
+        self.notify(id, Outcome::CrashLoop, Some(run));
+    }
+
+    /// Emails the output of a finished run, if the task has a `mail-to` and its
+    /// `mail-on` allows it.
+    fn mail(&mut self, id: &str, run: u64) {
+        let Some(entry) = self.entries.get(id) else {
+            return;
+        };
+        let config = &entry.task.config;
+        let Some(to) = config.mail_to.as_deref().filter(|to| !to.is_empty()) else {
+            return;
+        };
+        let Some(last) = entry.last.as_ref().filter(|l| l.run == run) else {
+            return;
+        };
+        let output = entry.output.tail(run, usize::MAX);
+        if !mail::wanted(config.mail_on.unwrap_or_default(), last, &output) {
+            return;
+        }
+
+        let from = config.mail_from.as_deref().unwrap_or("servum");
+        let message = mail::compose(id, to, from, last, &output, self.clock.now());
+        info!(
+            event = "mail.sent",
+            task = id,
+            run,
+            to,
+            "Emailing the output of task `{id}` to {to}"
+        );
+        self.processes
+            .mail(id, run, mail::argv(config.sendmail.as_ref()), message);
+    }
+
+    /// Sends each of a task's notifications for `outcome`, unless it is rate limited.
+    fn notify(&mut self, id: &str, outcome: Outcome, run: Option<u64>) {
+        let now = self.clock.now();
+        let Some(entry) = self.entries.get_mut(id) else {
+            return;
+        };
+
+        let mut notification = None;
+        for (i, notify) in entry.task.config.notify.iter().enumerate() {
+            if !notify.on.contains(&outcome)
+                || (outcome == Outcome::CrashLoop && entry.failed_runs != notify.crash_loop)
+            {
+                continue;
+            }
+            let limited = notify
+                .rate_limit
+                .zip(entry.notified.get(&i))
+                .is_some_and(|(limit, at)| now < *at + Duration::from_millis(limit as u64));
+            if limited {
+                info!(
+                    event = "notify.suppressed",
+                    task = id,
+                    outcome = outcome.as_str(),
+                    "Notification about task `{id}` was rate limited"
+                );
+                continue;
+            }
+
+            entry.notified.insert(i, now);
+            let notification = notification
+                .get_or_insert_with(|| Notification {
+                    task: id.to_owned(),
+                    outcome,
+                    time: now,
+                    run,
+                    exit_code: entry
+                        .last
+                        .as_ref()
+                        .filter(|l| Some(l.run) == run)
+                        .and_then(|l| l.exit_code),
+                    signal: entry
+                        .last
+                        .as_ref()
+                        .filter(|l| Some(l.run) == run)
+                        .and_then(|l| l.signal),
+                    output_tail: run
+                        .map(|run| entry.output.tail(run, OUTPUT_TAIL))
+                        .unwrap_or_default(),
+                })
+                .clone();
+            info!(
+                event = "notify.sent",
+                task = id,
+                outcome = outcome.as_str(),
+                action = notify.action.as_str(),
+                "Notifying about task `{id}` ({})",
+                outcome.as_str()
+            );
+            if let Err(err) = self.processes.notify(&entry.task, notify, notification) {
+                warn!(
+                    event = "notify.failed",
+                    task = id,
+                    outcome = outcome.as_str(),
+                    error = %format!("{err:#}"),
+                    "Failed to notify about task `{id}`: {err:#}"
+                );
+            }
+        }
+    }
+
+    /// Retries a failed run later, if the task's `retry` allows it.
+    fn schedule_retry(&mut self, id: &str, run: u64, trigger: Trigger, finished: DateTime<Local>) {
+        let Some(entry) = self.entries.get_mut(id) else {
+            return;
+        };
+        let Some(retry) = entry.task.config.retry.as_ref() else {
+            return;
+        };
+        let attempt = trigger.attempt();
+        let trigger = match trigger {
+            Trigger::Manual | Trigger::RunOnce => return,
+            Trigger::Retry { trigger, .. } => *trigger,
+            trigger => trigger,
+        };
+        if !entry.task.config.enabled || attempt > retry.attempts {
+            return;
+        }
+
+        let delay_ms = retry.delay(attempt);
+        info!(
+            event = "task.retrying",
+            task = id,
+            run,
+            attempt = attempt + 1,
+            delay_ms,
+            "Retrying task `{id}` in {}",
+            table::duration(delay_ms as u64)
+        );
+        entry.retry = Some((
+            finished + Duration::from_millis(delay_ms as u64),
+            Trigger::Retry {
+                trigger: Box::new(trigger),
+                attempt: attempt + 1,
+            },
+        ));
+    }
+
+    fn retry_due(&mut self, now: DateTime<Local>) {
+        if self.shutting_down {
+            return;
+        }
+        let mut due: Vec<_> = self
+            .entries
+            .iter_mut()
+            .filter_map(|(id, e)| {
+                let (_, trigger) = e.retry.take_if(|(at, _)| *at <= now)?;
+                Some((id.clone(), trigger))
+            })
+            .collect();
+
+        due.sort_by(|a, b| a.0.cmp(&b.0));
+        for (id, trigger) in due {
+            self.start(&id, trigger);
+        }
+    }
+
+    /// Starts every task in the `on-success`, `on-failure` and `on-complete` of
+    /// a task that has just finished.
+    fn start_triggered(&mut self, id: &str, last: &LastRun) {
+        let Some(config) = self.entries.get(id).map(|e| &e.task.config) else {
+            return;
+        };
+        let (on, listed) = if last.success {
+            (Finish::Success, &config.on_success)
+        } else {
+            (Finish::Failure, &config.on_failure)
+        };
+        let targets: Vec<_> = listed
+            .iter()
+            .map(|t| (on, t.clone()))
+            .chain(
+                config
+                    .on_complete
+                    .iter()
+                    .map(|t| (Finish::Complete, t.clone())),
+            )
+            .collect();
+
+        for (on, target) in targets {
+            let trigger = Trigger::Finished {
+                on,
+                task: id.to_owned(),
+                last: last.clone(),
+            };
+            if self
+                .entries
+                .get(&target)
+                .is_some_and(|e| e.task.config.enabled)
+            {
+                self.start(&target, trigger);
+            } else {
+                info!(
+                    event = "task.skipped",
+                    task = target,
+                    trigger = trigger.as_str(),
+                    reason = "disabled",
+                    "Task `{target}` is disabled, skipping"
+                );
+            }
+        }
+    }
+
+    fn ready(&mut self, id: &str, run: u64) {
+        let now = self.clock.now();
+        let Some(current) = self
+            .entries
+            .get_mut(id)
+            .and_then(|e| e.running.as_mut())
+            .filter(|r| r.id == run && !r.ready)
+        else {
+            return;
+        };
+        current.ready = true;
+        current.ready_by = None;
+        let duration_ms = u64::try_from((now - current.started).num_milliseconds()).unwrap_or(0);
+        info!(
+            event = "task.ready",
+            task = id,
+            run,
+            duration_ms,
+            "Task `{id}` is ready"
+        );
+
+        self.start_health(id, run);
+        self.start_waiting();
+    }
+
+    fn start_health(&mut self, id: &str, run: u64) {
+        let Some(entry) = self
+            .entries
+            .get(id)
+            .filter(|e| e.task.config.health.is_some())
+        else {
+            return;
+        };
+        let Some(current) = entry.running.as_ref().filter(|r| r.id == run) else {
+            return;
+        };
+
+        let started = self.processes.health(
+            id,
+            run,
+            &entry.task,
+            self.tx.clone(),
+            current.cancel.clone(),
+        );
+        if let Err(err) = started {
+            warn!(
+                event = "task.health_failed_to_start",
+                task = id,
+                run,
+                error = %format!("{err:#}"),
+                "Failed to start health checks for task `{id}`: {err:#}"
+            );
+        }
+    }
+
+    fn health(&mut self, id: &str, run: u64, healthy: bool) {
+        let Some(entry) = self.entries.get_mut(id) else {
+            return;
+        };
+        let Some(threshold) = entry
+            .task
+            .config
+            .health
+            .as_ref()
+            .map(|h| h.failure_threshold)
+        else {
+            return;
+        };
+        let Some(current) = entry
+            .running
+            .as_mut()
+            .filter(|r| r.id == run && !r.stopping)
+        else {
+            return;
+        };
+
+        if healthy {
+            current.failures = 0;
+            return;
+        }
+        current.failures += 1;
+        let failures = current.failures;
+        warn!(
+            event = "task.health_failed",
+            task = id,
+            run,
+            failures,
+            threshold,
+            "Health check of task `{id}` failed ({failures}/{threshold})"
+        );
+        if failures < threshold {
+            return;
+        }
+
+        current.cancel.store(true, Ordering::Relaxed);
+        entry.restart = Some(Trigger::Health);
+        warn!(
+            event = "task.unhealthy",
+            task = id,
+            run,
+            failures,
+            action = "restart",
+            "Task `{id}` is unhealthy, restarting it"
+        );
+        self.stop(id);
+    }
+
+    /// Starts queued tasks, highest priority first, while the limits allow.
+    fn start_queued(&mut self) {
+        if self.shutting_down {
+            return;
+        }
+        let mut queued: Vec<_> = self
+            .entries
+            .iter()
+            .filter_map(|(id, e)| {
+                let queued = e.queued.as_ref()?;
+                Some((e.task.config.priority, queued.since, id.clone()))
+            })
+            .collect();
+        queued.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+        for (_, _, id) in queued {
+            if self.full_limit(&id).is_some() {
+                continue;
+            }
+            if let Some(queued) = self.entries.get_mut(&id).and_then(|e| e.queued.take()) {
+                self.start(&id, queued.trigger);
+            }
+        }
+    }
+
+    /// The name of a limit that stops the task from being started right now, if any.
+    fn full_limit(&self, id: &str) -> Option<String> {
+        let running = || self.entries.values().filter(|e| e.running.is_some());
+        if self
+            .concurrency
+            .max
+            .is_some_and(|max| running().count() >= max)
+        {
+            return Some("max-concurrent".to_owned());
+        }
+
+        let entry = self.entries.get(id)?;
+        if let Some(lock) = &entry.task.config.lock {
+            let held = self
+                .entries
+                .values()
+                .any(|e| e.running.is_some() && e.task.config.lock.as_ref() == Some(lock));
+            if held {
+                return Some(lock.clone());
+            }
+        }
+
+        entry
+            .task
+            .config
+            .limits
+            .iter()
+            .find(|name| {
+                self.concurrency.limits.get(*name).is_some_and(|limit| {
+                    running()
+                        .filter(|e| e.task.config.limits.contains(name))
+                        .count()
+                        >= limit.max
+                })
+            })
+            .cloned()
+    }
+
+    /// Takes the `lock-file` of a task, returning `None` if it is held elsewhere or
+    /// couldn't be taken.
+    fn lock_file(&mut self, id: &str, path: &Path, trigger: &Trigger) -> Option<FileLock> {
+        match self.processes.lock(path) {
+            Ok(Some(lock)) => Some(lock),
+            Ok(None) => {
+                info!(
+                    event = "task.skipped",
+                    task = id,
+                    trigger = trigger.as_str(),
+                    reason = "locked",
+                    lock_file = %path.display(),
+                    "Lock file `{}` of task `{id}` is held elsewhere, skipping",
+                    path.display()
+                );
+                None
+            }
+            Err(err) => {
+                warn!(
+                    event = "task.start_failed",
+                    task = id,
+                    trigger = trigger.as_str(),
+                    error = %err,
+                    "Failed to lock `{}` for task `{id}`: {err}",
+                    path.display()
+                );
+                if matches!(trigger, Trigger::OnStart) {
+                    self.fail_strict(id);
+                }
+                None
+            }
+        }
+    }
+
+    fn enqueue(&mut self, id: &str, trigger: Trigger, limit: &str) {
+        let now = self.clock.now();
+        let Some(entry) = self.entries.get_mut(id) else {
+            return;
+        };
+        if entry.queued.is_some() {
+            info!(
+                event = "task.skipped",
+                task = id,
+                trigger = trigger.as_str(),
+                reason = "queued",
+                "Task `{id}` is already queued, skipping"
+            );
+            return;
+        }
+
+        let priority = entry.task.config.priority;
+        info!(
+            event = "task.queued",
+            task = id,
+            trigger = trigger.as_str(),
+            limit,
+            priority,
+            "Task `{id}` is queued until `{limit}` allows it to start"
+        );
+        let expires = entry
+            .task
+            .config
+            .queue_timeout
+            .map(|timeout| now + Duration::from_millis(timeout as u64));
+        entry.queued = Some(Queued {
+            trigger,
+            since: now,
+            expires,
+        });
+    }
+
+    /// Drops every queued start that has been waiting for longer than its `queue-timeout`.
+    fn expire_queued(&mut self, now: DateTime<Local>) {
+        let mut expired = vec![];
+        for (id, entry) in &mut self.entries {
+            let Some(queued) = entry
+                .queued
+                .take_if(|q| q.expires.is_some_and(|at| at <= now))
+            else {
+                continue;
+            };
+            let waited_ms = u64::try_from((now - queued.since).num_milliseconds()).unwrap_or(0);
+            warn!(
+                event = "task.skipped",
+                task = id,
+                trigger = queued.trigger.as_str(),
+                reason = "queue-timeout",
+                waited_ms,
+                "Task `{id}` was queued for too long, skipping"
+            );
+            expired.push(id.clone());
+        }
+
+        expired.sort();
+        for id in expired {
+            self.notify(&id, Outcome::Timeout, None);
+        }
+    }
+
+    /// Starts every task that was waiting on its requirements, if they are now ready.
+    fn start_waiting(&mut self) {
+        if self.shutting_down {
+            return;
+        }
+        for id in self.order.clone() {
+            if let Some(trigger) = self.entries.get_mut(&id).and_then(|e| e.waiting.take()) {
+                self.start(&id, trigger);
+            }
+        }
+    }
+
+    /// Starts watching the `watch-paths` and `restart-on-change` of a task.
+    fn watch(&mut self, id: &str) {
+        let Some(entry) = self.entries.get_mut(id) else {
+            return;
+        };
+        match self.processes.watch(id, &entry.task, self.tx.clone()) {
+            Ok(watchers) => entry.watchers = watchers,
+            Err(err) => warn!(
+                event = "task.watch_failed",
+                task = id,
+                error = %format!("{err:#}"),
+                "Failed to watch the paths of task `{id}`: {err:#}"
+            ),
+        }
+    }
+
+    fn changed(&mut self, id: &str, paths: Vec<PathBuf>) {
+        if self.shutting_down
+            || !self
+                .entries
+                .get(id)
+                .is_some_and(|e| e.task.config.enabled && !e.removed)
+        {
+            return;
+        }
+        self.start(id, Trigger::Watch { paths });
+    }
+
+    /// Restarts a task through its usual stop sequence, or starts it if it isn't running.
+    fn sources_changed(&mut self, id: &str, paths: Vec<PathBuf>) {
+        let Some(entry) = self
+            .entries
+            .get_mut(id)
+            .filter(|e| e.task.config.enabled && !e.removed)
+        else {
+            return;
+        };
+        if self.shutting_down {
+            return;
+        }
+
+        let joined: Vec<_> = paths.iter().map(|p| p.to_string_lossy()).collect();
+        let joined = joined.join("\n");
+        let run = entry.running.as_ref().map(|r| r.id);
+        info!(
+            event = "task.sources_changed",
+            task = id,
+            run,
+            paths = joined,
+            "Sources of task `{id}` changed"
+        );
+
+        match &entry.running {
+            Some(current) if current.stopping => {
+                entry.restart = Some(Trigger::Change { paths });
+            }
+            Some(_) => {
+                entry.restart = Some(Trigger::Change { paths });
+                self.stop(id);
+            }
+            None => {
+                self.start(id, Trigger::Change { paths });
+            }
+        }
+    }
+
+    /// Stops every running task that has `stop` set for a failed requirement.
+    fn requirement_failed(&mut self, id: &str) {
+        let dependents: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| {
+                e.task.config.requirement_failure == RequirementFailure::Stop
+                    && e.task.config.requires.iter().any(|r| r == id)
+                    && e.running.as_ref().is_some_and(|r| !r.stopping)
+            })
+            .map(|(dependent, _)| dependent.clone())
+            .collect();
+
+        for dependent in dependents {
+            warn!(
+                event = "task.requirement_failed",
+                task = dependent,
+                requirement = id,
+                action = "stop",
+                "Requirement `{id}` of task `{dependent}` failed, stopping it"
+            );
+            self.stop(&dependent);
+        }
+    }
+
+    /// Starts shutting down, returning how many tasks need to be stopped first.
+    fn shutdown(&mut self) -> usize {
+        self.shutting_down = true;
+        for entry in self.entries.values_mut() {
+            entry.waiting = None;
+            entry.queued = None;
+            entry.retry = None;
+        }
+        let running = self
+            .entries
+            .values()
+            .filter(|e| e.running.is_some())
+            .count();
+        self.stop_for_shutdown();
+        running
+    }
+
+    /// Stops every running task that no other running task depends on, so that
+    /// tasks are stopped in the reverse order to how they were started.
+    fn stop_for_shutdown(&mut self) {
+        let stoppable: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| e.running.as_ref().is_some_and(|r| !r.stopping))
+            .filter(|(id, _)| {
+                !self.entries.values().any(|other| {
+                    other.running.is_some() && other.task.dependencies().any(|d| d == *id)
+                })
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        for id in stoppable {
+            self.stop(&id);
+        }
+    }
+
+    fn output_closed(&mut self, id: &str, run: u64) {
+        let exited = self
+            .entries
+            .get_mut(id)
+            .and_then(|e| e.running.as_mut())
+            .filter(|r| r.id == run)
+            .and_then(|r| r.status.take());
+        if let Some(status) = exited {
+            self.finish(id, run, status);
+        }
+
+        let Some(entry) = self.entries.get_mut(id) else {
+            return;
+        };
+        entry
+            .output
+            .buffer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .end(run);
+
+        let last = entry.last.clone().filter(|l| l.run == run);
+        let (done, waiting) = mem::take(&mut entry.waiters)
+            .into_iter()
+            .partition(|(r, _)| *r == run);
+        entry.waiters = waiting;
+
+        if let Some(last) = last {
+            for (_, reply) in done {
+                let _ = reply.send(Response::Finished(last.clone()));
+            }
+        }
+        self.record(id, Some(run));
+    }
+
+    /// Records the finished runs of a task in the history, once their output
+    /// has closed, or every one that is waiting if `run` is `None`.
+    fn record(&mut self, id: &str, run: Option<u64>) {
+        let now = self.clock.now();
+        let (Some(entry), Some(history)) = (self.entries.get_mut(id), self.history.as_mut()) else {
+            return;
+        };
+        let (done, waiting) = mem::take(&mut entry.unrecorded)
+            .into_iter()
+            .partition(|r| run.is_none_or(|run| r.run == run));
+        entry.unrecorded = waiting;
+
+        for mut record in done {
+            record.output_tail = entry.output.tail(record.run, OUTPUT_TAIL);
+            if let Err(err) = history.record(&record, now) {
+                warn!(
+                    event = "history.failed",
+                    task = id,
+                    run = record.run,
+                    error = %format!("{err:#}"),
+                    "Failed to record run {} of task `{id}`: {err:#}",
+                    record.run
+                );
+            }
+        }
+    }
+
+    /// Reloads the config file, returning a summary of what changed.
+    fn reload(&mut self) -> eyre::Result<String> {
+        let result = crate::load_config(&self.config).and_then(|config| self.apply(config));
+        if let Err(err) = &result {
+            warn!(event = "config.reload_failed", error = %format!("{err:#}"), "Failed to reload config: {err:#}");
+        }
+        result
+    }
+
+    fn apply(&mut self, config: Config) -> eyre::Result<String> {
+        let concurrency = config.concurrency();
+        let (_, tasks): (_, HashMap<String, ResolvedTask>) = config.try_into()?;
+        let order = config::dependency_order(&tasks)?;
+
+        let now = self.clock.now();
+        let mut removed: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(id, e)| !e.removed && !tasks.contains_key(*id))
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        let mut entries = HashMap::new();
+        for (id, task) in tasks {
+            let old = self.entries.get(&id).filter(|e| !e.removed);
+            if old.is_some_and(|old| old.task == task) {
+                continue;
+            }
+
+            let mut entry = Entry::new(&id, task, now)?;
+            if let Some(foreground) = &self.foreground {
+                entry.output.label(&foreground.label(&id));
+            }
+            entries.insert(id, entry);
+        }
+
+        // Everything is valid, so the new config can be swapped in.
+        self.order = order;
+        self.concurrency = concurrency;
+        let (mut added, mut changed) = (vec![], vec![]);
+        for (id, mut entry) in entries {
+            match self.entries.remove(&id) {
+                Some(old) if !old.removed => {
+                    entry.carry_over(old);
+                    changed.push(id.clone());
+                }
+                old => {
+                    // A removed task that is still stopping is now being tracked
+                    // again, so just keep the running process.
+                    entry.running = old.and_then(|old| old.running);
+                    added.push(id.clone());
+                }
+            }
+            if entry.task.config.enabled {
+                scheduled(&id, entry.next);
+            }
+            self.entries.insert(id.clone(), entry);
+            self.watch(&id);
+        }
+
+        for id in &removed {
+            let Some(entry) = self.entries.get_mut(id) else {
+                continue;
+            };
+            if entry.running.is_some() {
+                entry.removed = true;
+                entry.task.config.enabled = false;
+                entry.watchers.clear();
+            } else {
+                self.entries.remove(id);
+            }
+        }
+        self.track(now);
+
+        added.sort();
+        removed.sort();
+        changed.sort();
+        let summary = format!(
+            "{} added, {} removed, {} changed",
+            added.len(),
+            removed.len(),
+            changed.len()
+        );
+        info!(
+            event = "config.reloaded",
+            added = added.join(","),
+            removed = removed.join(","),
+            changed = changed.join(","),
+            "Config reloaded ({summary})"
+        );
+
+        let stopping: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| !e.task.config.enabled && e.running.is_some())
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in stopping {
+            self.stop(&id);
+        }
+        self.start_queued();
+
+        Ok(summary)
+    }
+
+    fn control(&mut self, command: Command, reply: &Sender<Response>) {
+        let response = match command {
+            Command::Status => Response::Status {
+                tasks: self.status(),
+            },
+            Command::Start { task } => self.with_entry(&task, |s, entry| {
+                if let Some(run) = entry.running {
+                    return Err(format!("Task `{task}` is already running (run {run})"));
+                }
+                if !entry.enabled {
+                    return Err(format!("Task `{task}` is disabled"));
+                }
+                s.start_manual(&task)
+            }),
+            Command::Stop { task } => self.with_entry(&task, |s, entry| {
+                if entry.running.is_none() {
+                    return Err(format!("Task `{task}` is not running"));
+                }
+                s.stop(&task);
+                Ok(format!("Stopping `{task}`"))
+            }),
+            Command::Restart { task } => self.with_entry(&task, |s, entry| {
+                if !entry.enabled {
+                    return Err(format!("Task `{task}` is disabled"));
+                }
+                if entry.running.is_none() {
+                    return s.start_manual(&task);
+                }
+                if let Some(entry) = s.entries.get_mut(&task) {
+                    entry.restart = Some(Trigger::Manual);
+                }
+                s.stop(&task);
+                Ok(format!("Restarting `{task}`"))
+            }),
+            Command::Enable { task } => self.with_entry(&task, |s, _| {
+                let now = s.clock.now();
+                if let Some(entry) = s.entries.get_mut(&task).filter(|e| !e.task.config.enabled) {
+                    entry.task.config.enabled = true;
+                    entry.next = entry
+                        .schedule
+                        .as_ref()
+                        .and_then(|schedule| schedule.after(&now).next());
+                    scheduled(&task, entry.next);
+                }
+                Ok(format!("Enabled `{task}`"))
+            }),
+            Command::Disable { task } => self.with_entry(&task, |s, _| {
+                if let Some(entry) = s.entries.get_mut(&task) {
+                    entry.task.config.enabled = false;
+                    entry.waiting = None;
+                    entry.queued = None;
+                    entry.retry = None;
+                }
+                s.stop(&task);
+                Ok(format!("Disabled `{task}`"))
+            }),
+            Command::Reload => match self.reload() {
+                Ok(summary) => Response::Ok {
+                    message: format!("Config reloaded ({summary})"),
+                },
+                Err(err) => Response::Error {
+                    message: format!("{err:?}"),
+                },
+            },
+            Command::Shutdown => {
+                let running = self.shutdown();
+                Response::Ok {
+                    message: format!("Shutting down, stopping {running} task(s)"),
+                }
+            }
+            Command::Logs { task, run, follow } => {
+                self.logs(&task, run, follow, reply);
+                return;
+            }
+            Command::RunOnce { task } => self.with_entry(&task, |s, entry| {
+                if let Some(run) = entry.running {
+                    return Err(format!("Task `{task}` is already running (run {run})"));
+                }
+                if !entry.enabled {
+                    return Err(format!("Task `{task}` is disabled"));
+                }
+                s.run_once(&task, reply)
+            }),
+        };
+
+        let _ = reply.send(response);
+    }
+
+    /// Sends the buffered output of a task, optionally following new output.
+    fn logs(&self, task: &str, run: Option<u64>, follow: bool, reply: &Sender<Response>) {
+        let Some(entry) = self.entries.get(task).filter(|e| !e.removed) else {
+            let _ = reply.send(unknown(task));
+            return;
+        };
+
+        let mut buffer = entry
+            .output
+            .buffer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for line in buffer.lines(run) {
+            let _ = reply.send(Response::Line(line.clone()));
+        }
+
+        if follow {
+            let reply = reply.clone();
+            buffer.follow(run, move |line| {
+                reply.send(Response::Line(line.clone())).is_ok()
+            });
+        }
+    }
+
+    /// Runs `f` with the given task if it exists, turning the result into a response.
+    fn with_entry(
+        &mut self,
+        task: &str,
+        f: impl FnOnce(&mut Self, EntryInfo) -> Result<String, String>,
+    ) -> Response {
+        let Some(entry) = self.entries.get(task).filter(|e| !e.removed) else {
+            return unknown(task);
+        };
+        let info = EntryInfo {
+            running: entry.running.as_ref().map(|r| r.id),
+            enabled: entry.task.config.enabled,
+        };
+
+        match f(self, info) {
+            Ok(message) => Response::Ok { message },
+            Err(message) => Response::Error { message },
+        }
+    }
+
+    fn start_manual(&mut self, task: &str) -> Result<String, String> {
+        match self.start(task, Trigger::Manual) {
+            Some(run) => Ok(format!("Started `{task}` (run {run})")),
+            None if self.entries.get(task).is_some_and(|e| e.waiting.is_some()) => Ok(format!(
+                "`{task}` will start once its requirements are ready"
+            )),
+            None if self.entries.get(task).is_some_and(|e| e.queued.is_some()) => Ok(format!(
+                "`{task}` is queued until a limit allows it to start"
+            )),
+            None => Err(format!(
+                "Failed to start `{task}`, see the event log for details"
+            )),
+        }
+    }
+
+    /// Starts a task, streaming its output to `reply` and then its result once it finishes.
+    fn run_once(&mut self, task: &str, reply: &Sender<Response>) -> Result<String, String> {
+        let Some(entry) = self.entries.get_mut(task) else {
+            return Err(format!("Unknown task `{task}`"));
+        };
+
+        // Follow before starting so that no output is missed, even when nothing is buffered.
+        let run = self.next_run;
+        let follower = reply.clone();
+        entry
+            .output
+            .buffer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .follow(Some(run), move |line| {
+                follower.send(Response::Line(line.clone())).is_ok()
+            });
+        entry.waiters.push((run, reply.clone()));
+
+        if self.start(task, Trigger::RunOnce).is_some() {
+            return Ok(format!("Started `{task}` (run {run})"));
+        }
+
+        let (mut waiting, mut queued) = (false, false);
+        if let Some(entry) = self.entries.get_mut(task) {
+            // Runs are numbered when they start, so a run can't wait on its requirements.
+            waiting = entry.waiting.take().is_some();
+            queued = entry.queued.take().is_some();
+            entry.waiters.retain(|(r, _)| *r != run);
+            entry
+                .output
+                .buffer
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .end(run);
+        }
+        if waiting {
+            return Err(format!("The requirements of `{task}` aren't ready yet"));
+        }
+        if queued {
+            return Err(format!("`{task}` can't be started until a limit allows it"));
+        }
+        Err(format!(
+            "Failed to start `{task}`, see the event log for details"
+        ))
+    }
+
+    fn status(&self) -> Vec<TaskStatus> {
+        let mut tasks: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| !e.removed)
+            .map(|(id, e)| TaskStatus {
+                task: id.clone(),
+                name: e.task.config.name.clone(),
+                state: match &e.running {
+                    Some(run) if run.stopping => TaskState::Stopping,
+                    Some(run) if !run.ready => TaskState::Starting,
+                    Some(_) => TaskState::Running,
+                    None if !e.task.config.enabled => TaskState::Disabled,
+                    None if e.queued.is_some() => TaskState::Queued,
+                    None if e.last.as_ref().is_some_and(|l| !l.success) => TaskState::Failed,
+                    None => TaskState::Idle,
+                },
+                pid: e.running.as_ref().map(|r| r.pid),
+                started: e.running.as_ref().map(|r| r.started),
+                last: e.last.clone(),
+                next: e
+                    .next
+                    .into_iter()
+                    .chain(e.retry.as_ref().map(|(at, _)| *at))
+                    .min()
+                    .filter(|_| e.task.config.enabled),
+                restarts: e.restarts,
+            })
+            .collect();
+        tasks.sort_by(|a, b| a.task.cmp(&b.task));
+        tasks
+    }
+
+    /// When something will next be due, if anything.
+    pub fn next_deadline(&self) -> Option<DateTime<Local>> {
+        // Nothing fires while shutting down, so waiting on it would only spin.
+        let fires = self
+            .entries
+            .values()
+            .filter(|e| e.task.config.enabled && !self.shutting_down)
+            .filter_map(|e| e.next);
+        let kills = self
+            .entries
+            .values()
+            .filter_map(|e| e.running.as_ref()?.kill_at);
+
+        let ready = self
+            .entries
+            .values()
+            .filter_map(|e| e.running.as_ref()?.ready_by);
+        let output = self
+            .entries
+            .values()
+            .filter_map(|e| e.running.as_ref()?.output_by);
+        let queued = self
+            .entries
+            .values()
+            .filter_map(|e| e.queued.as_ref()?.expires);
+        let retries = self
+            .entries
+            .values()
+            .filter_map(|e| Some(e.retry.as_ref()?.0));
+        let overdue = self
+            .entries
+            .iter()
+            .filter(|(_, e)| !e.overdue)
+            .filter_map(|(id, e)| self.success_due(id, e));
+
+        fires
+            .chain(kills)
+            .chain(ready)
+            .chain(output)
+            .chain(queued)
+            .chain(retries)
+            .chain(overdue)
+            .min()
+    }
+
+    /// When a task has to have succeeded by, according to its
+    /// `expect-success-within`.
+    fn success_due(&self, id: &str, entry: &Entry) -> Option<DateTime<Local>> {
+        let window = entry.task.config.expect_success_within?;
+        let saved = self.state.tasks.get(id)?;
+        Some(saved.last_success.unwrap_or(saved.since) + Duration::from_millis(window as u64))
+    }
+
+    /// Sends an `overdue` notification for each task that hasn't succeeded
+    /// within its `expect-success-within`, whether or not it is enabled.
+    fn check_overdue(&mut self, now: DateTime<Local>) {
+        let overdue: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| !e.overdue && !e.removed)
+            .filter(|(id, e)| self.success_due(id, e).is_some_and(|due| due <= now))
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        for id in overdue {
+            let Some(entry) = self.entries.get_mut(&id) else {
+                continue;
+            };
+            entry.overdue = true;
+            let window_ms = entry.task.config.expect_success_within.unwrap_or_default();
+            let last_success = self.state.tasks.get(&id).and_then(|s| s.last_success);
+            warn!(
+                event = "task.overdue",
+                task = id,
+                last_success = last_success.map(|t| t.to_rfc3339()),
+                window_ms,
+                "Task `{id}` hasn't succeeded within {}",
+                table::duration(window_ms as u64)
+            );
+            self.notify(&id, Outcome::Overdue, None);
+        }
+    }
+
+    /// Starts tracking when each task with `expect-success-within` last
+    /// succeeded, and stops tracking those without it.
+    fn track(&mut self, now: DateTime<Local>) {
+        let before = self.state.clone();
+        let tracked: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| !e.removed && e.task.config.expect_success_within.is_some())
+            .map(|(id, _)| id.clone())
+            .collect();
+        self.state.tasks.retain(|id, _| tracked.contains(id));
+        for id in tracked {
+            self.state.tasks.entry(id).or_insert(SavedTask {
+                since: now,
+                last_success: None,
+            });
+        }
+        if self.state != before {
+            self.save_state();
+        }
+    }
+
+    /// Saves the state to the `state-file`, if it is being persisted.
+    fn save_state(&self) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        if let Err(err) = self.state.save(path) {
+            warn!(
+                event = "state.save_failed",
+                path = %path.display(),
+                error = %format!("{err:#}"),
+                "Failed to save state: {err:#}"
+            );
+        }
+    }
+
+    fn fire_due(&mut self, now: DateTime<Local>) {
+        if self.shutting_down {
+            return;
+        }
+        let mut due = vec![];
+
+        for (id, entry) in &mut self.entries {
+            let Some(next) = entry.next.filter(|next| *next <= now) else {
+                continue;
+            };
+            if !entry.task.config.enabled {
+                continue;
+            }
+
+            entry.next = entry.schedule.as_ref().and_then(|s| s.after(&now).next());
+            scheduled(id, entry.next);
+            due.push((id.clone(), next));
+        }
+
+        // Keep the order predictable when several tasks are due at once.
+        due.sort();
+        for (id, scheduled) in due {
+            self.start(&id, Trigger::Cron { scheduled });
+        }
+    }
+
+    fn kill_overdue(&mut self, now: DateTime<Local>) {
+        let overdue: Vec<_> = self
+            .entries
+            .iter()
+            .filter_map(|(id, e)| {
+                let run = e.running.as_ref()?;
+                run.kill_at
+                    .is_some_and(|at| at <= now)
+                    .then(|| (id.clone(), run.id))
+            })
+            .collect();
+
+        for (id, run) in overdue {
+            self.kill(&id, run, "timeout");
+        }
+    }
+
+    fn ready_overdue(&mut self, now: DateTime<Local>) {
+        let overdue: Vec<_> = self
+            .entries
+            .iter()
+            .filter_map(|(id, e)| {
+                let run = e.running.as_ref()?;
+                run.ready_by
+                    .is_some_and(|at| at <= now)
+                    .then(|| (id.clone(), run.id))
+            })
+            .collect();
+
+        for (id, run) in overdue {
+            self.ready_failed(&id, run, "timeout", None);
+        }
+    }
+
+    /// Finishes every run whose output is still open long after its process
+    /// exited, with whatever output it has so far.
+    fn close_overdue_output(&mut self, now: DateTime<Local>) {
+        let overdue: Vec<_> = self
+            .entries
+            .iter()
+            .filter_map(|(id, e)| {
+                let run = e.running.as_ref()?;
+                run.output_by
+                    .is_some_and(|at| at <= now)
+                    .then(|| (id.clone(), run.id))
+            })
+            .collect();
+
+        for (id, run) in overdue {
+            warn!(
+                event = "task.output_timeout",
+                task = id,
+                run,
+                waited_ms = u64::try_from(OUTPUT_GRACE.as_millis()).unwrap_or(u64::MAX),
+                "Output of task `{id}` is still open after it exited, finishing without the rest"
+            );
+            self.output_closed(&id, run);
+        }
+    }
+
+    /// Starts a task, returning the id of the run if it was started.
+    ///
+    /// If any of its requirements are still starting up, the task is started
+    /// once they are ready instead.
+    fn start(&mut self, id: &str, trigger: Trigger) -> Option<u64> {
+        let run = self.next_run;
+        if !self.requirements_met(id, &trigger) {
+            return None;
+        }
+
+        if let Some(current) = &self.entries.get(id)?.running {
+            info!(
+                event = "task.skipped",
+                task = id,
+                trigger = trigger.as_str(),
+                reason = "overlap",
+                running_run = current.id,
+                "Task `{id}` is still running, skipping"
+            );
+            return None;
+        }
+        if let Some(limit) = self.full_limit(id) {
+            self.enqueue(id, trigger, &limit);
+            return None;
+        }
+        let lock_file = match self.entries.get(id)?.task.config.lock_file.clone() {
+            Some(path) => Some(self.lock_file(id, &path, &trigger)?),
+            None => None,
+        };
+
+        let entry = self.entries.get_mut(id)?;
+
+        match self.processes.spawn(
+            id,
+            run,
+            &entry.task,
+            &trigger,
+            &entry.output,
+            self.tx.clone(),
+        ) {
+            Ok((pid, argv)) => {
+                self.next_run += 1;
+                // Only a run that actually started replaces a pending retry.
+                if let Some((_, retry)) = entry.retry.take() {
+                    info!(
+                        event = "task.retry_cancelled",
+                        task = id,
+                        attempt = retry.attempt(),
+                        trigger = trigger.as_str(),
+                        "Pending retry of task `{id}` was replaced by a new start"
+                    );
+                }
+                let started = self.clock.now();
+                let ready_by = entry
+                    .task
+                    .config
+                    .ready
+                    .as_ref()
+                    .map(|ready| started + Duration::from_millis(ready.timeout as u64));
+                let attempt = trigger.attempt();
+                entry.running = Some(Run {
+                    id: run,
+                    pid,
+                    trigger: trigger.clone(),
+                    started,
+                    stopping: false,
+                    kill_at: None,
+                    ready: ready_by.is_none(),
+                    ready_by,
+                    failures: 0,
+                    cancel: Arc::new(AtomicBool::new(false)),
+                    _lock_file: lock_file,
+                    matched: None,
+                    status: None,
+                    output_by: None,
+                });
+                info!(
+                    event = "task.started",
+                    task = id,
+                    run,
+                    trigger = trigger.as_str(),
+                    triggered_by = trigger.source().map(|(task, _)| task),
+                    triggered_by_run = trigger.source().map(|(_, run)| run),
+                    attempt,
+                    pid,
+                    argv = serde_json::to_string(&argv).unwrap_or_default(),
+                    "Task `{id}` started (pid {pid})"
+                );
+
+                self.match_output(id, run);
+                self.start_checks(id, run);
+                Some(run)
+            }
+            Err(err) => {
+                warn!(
+                    event = "task.start_failed",
+                    task = id,
+                    trigger = trigger.as_str(),
+                    error = %format!("{err:#}"),
+                    "Failed to start task `{id}`: {err:#}"
+                );
+                if matches!(trigger, Trigger::OnStart) {
+                    self.fail_strict(id);
+                }
+                None
+            }
+        }
+    }
+
+    /// Whether every requirement of a task is ready, otherwise either waiting for
+    /// them or skipping the start.
+    fn requirements_met(&mut self, id: &str, trigger: &Trigger) -> bool {
+        let Some(entry) = self.entries.get(id) else {
+            return false;
+        };
+        for requirement in &entry.task.config.requires {
+            match self.requirement_state(requirement) {
+                Requirement::Ready => {}
+                Requirement::Starting => {
+                    info!(
+                        event = "task.waiting",
+                        task = id,
+                        trigger = trigger.as_str(),
+                        requirement,
+                        "Task `{id}` is waiting for requirement `{requirement}` to be ready"
+                    );
+                    if let Some(entry) = self.entries.get_mut(id) {
+                        entry.waiting = Some(trigger.clone());
+                    }
+                    return false;
+                }
+                Requirement::Unmet => {
+                    info!(
+                        event = "task.skipped",
+                        task = id,
+                        trigger = trigger.as_str(),
+                        reason = "requirement",
+                        requirement,
+                        "Requirement `{requirement}` of task `{id}` isn't running, skipping"
+                    );
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Starts checking the output of a new run against the task's `fail-on-output`
+    /// and `succeed-on-output`.
+    fn match_output(&mut self, id: &str, run: u64) {
+        let Some(entry) = self.entries.get_mut(id) else {
+            return;
+        };
+        let Some(current) = entry.running.as_mut().filter(|r| r.id == run) else {
+            return;
+        };
+        let (fail, succeed) = (
+            entry.fail_on_output.clone(),
+            entry.succeed_on_output.clone(),
+        );
+        if fail.is_none() && succeed.is_none() {
+            return;
+        }
+
+        let matched = Arc::new(OutputMatched::default());
+        current.matched = Some(matched.clone());
+        let check = move |line: &Line| {
+            if fail.as_ref().is_some_and(|r| r.is_match(&line.text)) {
+                matched.fail.store(true, Ordering::Relaxed);
+            }
+            if succeed.as_ref().is_some_and(|r| r.is_match(&line.text)) {
+                matched.succeed.store(true, Ordering::Relaxed);
+            }
+            true
+        };
+
+        // Output may have been read since the process was started.
+        let mut buffer = entry
+            .output
+            .buffer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for line in buffer.lines(Some(run)) {
+            check(line);
+        }
+        buffer.follow(Some(run), check);
+    }
+
+    /// Starts checking whether a new run is ready, or its health if it already is.
+    fn start_checks(&mut self, id: &str, run: u64) {
+        let Some(entry) = self.entries.get(id) else {
+            return;
+        };
+        let Some(current) = entry.running.as_ref().filter(|r| r.id == run) else {
+            return;
+        };
+        if current.ready {
+            self.start_health(id, run);
+            return;
+        }
+
+        let checking = self.processes.ready(
+            id,
+            run,
+            &entry.task,
+            &entry.output,
+            self.tx.clone(),
+            current.cancel.clone(),
+        );
+        if let Err(err) = checking {
+            self.ready_failed(id, run, "error", Some(&format!("{err:#}")));
+        }
+    }
+
+    fn requirement_state(&self, id: &str) -> Requirement {
+        let Some(entry) = self.entries.get(id) else {
+            return Requirement::Unmet;
+        };
+        match &entry.running {
+            Some(run) if run.ready => Requirement::Ready,
+            Some(run) if !run.stopping => Requirement::Starting,
+            None if entry.last.as_ref().is_some_and(|l| l.success) => Requirement::Ready,
+            Some(_) | None => Requirement::Unmet,
+        }
+    }
+
+    /// Gives up on a run that didn't become ready, stopping it and anything
+    /// that requires it.
+    fn ready_failed(&mut self, id: &str, run: u64, reason: &str, error: Option<&str>) {
+        let Some(current) = self
+            .entries
+            .get_mut(id)
+            .and_then(|e| e.running.as_mut())
+            .filter(|r| r.id == run)
+        else {
+            return;
+        };
+        current.cancel.store(true, Ordering::Relaxed);
+        current.ready_by = None;
+        let startup = matches!(current.trigger, Trigger::OnStart);
+        warn!(
+            event = "task.ready_failed",
+            task = id,
+            run,
+            reason,
+            error,
+            "Task `{id}` didn't become ready ({reason}), stopping it"
+        );
+
+        self.stop(id);
+        if reason == "timeout" {
+            self.notify(id, Outcome::Timeout, Some(run));
+        }
+        if startup {
+            self.fail_strict(id);
+        }
+        self.requirement_failed(id);
+        self.start_waiting();
+    }
+
+    /// Asks a running task to stop, according to its `cmd-stop` and `stop-timeout`.
+    fn stop(&mut self, id: &str) {
+        let Some(entry) = self.entries.get_mut(id) else {
+            return;
+        };
+        let Some(run) = entry
+            .running
+            .as_mut()
+            .filter(|r| !r.stopping && r.status.is_none())
+        else {
+            return;
+        };
+        let timeout = entry.task.config.stop_timeout;
+
+        let method = match entry.task.stop_argv() {
+            Some(argv) => {
+                let run_id = run.id;
+                let spawned = argv.and_then(|argv| {
+                    // Not waited on when there is no timeout.
+                    Ok(self.processes.spawn_stop(
+                        id,
+                        run_id,
+                        &entry.task,
+                        &argv,
+                        timeout > 0,
+                        self.tx.clone(),
+                    )?)
+                });
+
+                if let Err(err) = spawned {
+                    warn!(
+                        event = "task.stop_cmd_failed",
+                        task = id,
+                        run = run_id,
+                        error = %format!("{err:#}"),
+                        "Failed to run `cmd-stop` for task `{id}`: {err:#}"
+                    );
+                    self.kill(id, run_id, "cmd-stop-failed");
+                    return;
+                }
+                "cmd-stop"
+            }
+            None if timeout == 0 => {
+                self.processes.signal(run.pid, libc::SIGKILL);
+                "kill"
+            }
+            None => {
+                self.processes.signal(run.pid, libc::SIGINT);
+                "signal"
+            }
+        };
+
+        run.stopping = true;
+        if timeout > 0 {
+            run.kill_at = Some(self.clock.now() + Duration::from_millis(timeout as u64));
+        }
+        info!(
+            event = "task.stop_requested",
+            task = id,
+            run = run.id,
+            pid = run.pid,
+            method,
+            timeout_ms = timeout,
+            "Stopping task `{id}` ({method})"
+        );
+    }
+
+    fn kill(&mut self, id: &str, run: u64, reason: &str) {
+        let Some(current) = self
+            .entries
+            .get_mut(id)
+            .and_then(|e| e.running.as_mut())
+            .filter(|r| r.id == run && r.status.is_none())
+        else {
+            return;
+        };
+
+        self.processes.signal(current.pid, libc::SIGKILL);
+        current.stopping = true;
+        current.kill_at = None;
+        warn!(
+            event = "task.killed",
+            task = id,
+            run,
+            pid = current.pid,
+            reason,
+            "Killed task `{id}` ({reason})"
+        );
+    }
+}
+
+impl Entry {
+    fn new(id: &str, task: ResolvedTask, now: DateTime<Local>) -> eyre::Result<Self> {
+        let schedule = task
+            .config
+            .cron
+            .as_deref()
+            .map(Schedule::from_str)
+            .transpose()
+            .wrap_err_with(|| format!("Invalid cron for task `{id}`"))?;
+        if let Some(ready) = &task.config.ready {
+            validate_check(&ready.check)
+                .wrap_err_with(|| format!("Invalid `ready` for task `{id}`"))?;
+        }
+        if let Some(health) = &task.config.health {
+            if let Check::Stdout(_) = health.check {
+                eyre::bail!(
+                    "Invalid `health` for task `{id}`: stdout can only be checked by `ready`"
+                );
+            }
+            validate_check(&health.check)
+                .wrap_err_with(|| format!("Invalid `health` for task `{id}`"))?;
+        }
+        for (key, value) in [
+            ("mail-to", &task.config.mail_to),
+            ("mail-from", &task.config.mail_from),
+        ] {
+            if value.as_ref().is_some_and(|v| v.contains(['\r', '\n'])) {
+                eyre::bail!("Invalid `{key}` for task `{id}`: it can't contain line breaks");
+            }
+        }
+        for notify in &task.config.notify {
+            if let NotifyAction::Url(url) = &notify.action {
+                HttpUrl::parse(url)
+                    .wrap_err_with(|| format!("Invalid `notify` for task `{id}`"))?;
+            }
+        }
+        let cwd = env::current_dir()?;
+        Matcher::new(&task.config.watch_paths, &task.config.watch_events, &cwd)
+            .wrap_err_with(|| format!("Invalid `watch-paths` for task `{id}`"))?;
+        Matcher::new(&task.config.restart_on_change, &FsEvent::ALL, &cwd)
+            .wrap_err_with(|| format!("Invalid `restart-on-change` for task `{id}`"))?;
+        let fail_on_output = task
+            .config
+            .fail_on_output
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .wrap_err_with(|| format!("Invalid `fail-on-output` for task `{id}`"))?;
+        let succeed_on_output = task
+            .config
+            .succeed_on_output
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .wrap_err_with(|| format!("Invalid `succeed-on-output` for task `{id}`"))?;
+        let next = schedule.as_ref().and_then(|s| s.after(&now).next());
+        let output = Output::new(id, task.log.as_ref());
+
+        Ok(Self {
+            task,
+            schedule,
+            next,
+            output,
+            running: None,
+            removed: false,
+            restart: None,
+            restarts: 0,
+            last: None,
+            waiters: vec![],
+            waiting: None,
+            queued: None,
+            retry: None,
+            failed_runs: 0,
+            notified: HashMap::new(),
+            overdue: false,
+            unrecorded: vec![],
+            watchers: vec![],
+            fail_on_output,
+            succeed_on_output,
+        })
+    }
+
+    /// Takes over the state of the task from before the config was reloaded.
+    fn carry_over(&mut self, old: Entry) {
+        // Keep rate limiting the notifications that are still there,
+        // even if they have moved.
+        self.notified = old
+            .notified
+            .iter()
+            .filter_map(|(i, at)| {
+                let notify = old.task.config.notify.get(*i)?;
+                let i = self.task.config.notify.iter().position(|n| n == notify)?;
+                Some((i, *at))
+            })
+            .collect();
+        self.running = old.running;
+        self.restarts = old.restarts;
+        self.last = old.last;
+        self.waiters = old.waiters;
+        self.waiting = old.waiting;
+        self.queued = old.queued;
+        self.retry = old.retry;
+        self.failed_runs = old.failed_runs;
+        self.overdue = old.overdue;
+        self.unrecorded = old.unrecorded;
+        self.output.keep_buffer(&old.output);
+    }
+}
+
+/// Checks the parts of a check that can't be checked while parsing.
+fn validate_check(check: &Check) -> eyre::Result<()> {
+    match check {
+        Check::Stdout(pattern) => {
+            Regex::new(pattern)?;
+        }
+        Check::Http(url) => {
+            HttpUrl::parse(url)?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Whether a required task is ready for the tasks that require it to start.
+enum Requirement {
+    Ready,
+    /// Running, but its `ready` check hasn't passed yet.
+    Starting,
+    Unmet,
+}
+
+/// A snapshot of an entry, for use while the scheduler is mutably borrowed.
+struct EntryInfo {
+    running: Option<u64>,
+    enabled: bool,
+}
+
+/// The history record of a finished run, without its output tail.
+fn history_record(id: &str, run: &Run, last: &LastRun) -> history::Record {
+    history::Record {
+        run: run.id,
+        task: id.to_owned(),
+        trigger: run.trigger.as_str().to_owned(),
+        attempt: run.trigger.attempt(),
+        scheduled: run.trigger.scheduled(),
+        started: run.started,
+        finished: last.finished,
+        success: last.success,
+        exit_code: last.exit_code,
+        signal: last.signal,
+        duration_ms: last.duration_ms,
+        output_tail: vec![],
+    }
+}
+
+fn unknown(task: &str) -> Response {
+    Response::Error {
+        message: format!("Unknown task `{task}`"),
+    }
+}
+
+fn scheduled(id: &str, next: Option<DateTime<Local>>) {
+    if let Some(next) = next {
+        info!(
+            event = "task.scheduled",
+            task = id,
+            next = next.to_rfc3339(),
+            "Task `{id}` scheduled for {next}"
+        );
+    }
+}
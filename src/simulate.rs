@@ -0,0 +1,388 @@
+#[cfg(test)]
+mod test;
+
+use std::{
+    cell::RefCell,
+    fmt, io,
+    os::unix::process::ExitStatusExt,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    rc::Rc,
+    sync::{atomic::AtomicBool, mpsc::Sender, Arc, Mutex, PoisonError},
+};
+
+use chrono::{DateTime, Local, TimeDelta};
+use color_eyre::eyre;
+use hashbrown::{HashMap, HashSet};
+use notify::Watcher;
+use tracing::{
+    field::{Field, Visit},
+    Subscriber,
+};
+use tracing_subscriber::{layer::Context, prelude::*, Layer, Registry};
+
+use crate::{
+    clock::{Clock, VirtualClock},
+    config::{Concurrency, Notify, ResolvedTask},
+    log::{Buffer, Line, Output, Stream},
+    notification::Notification,
+    process::{FileLock, Processes},
+    scheduler::{Event, Scheduler, Trigger},
+};
+
+/// How long stub processes should take to exit, and how they exit.
+#[derive(Debug, Clone, Default)]
+pub struct Durations {
+    pub tasks: HashMap<String, TimeDelta>,
+    pub default: TimeDelta,
+    /// The exit code of each task that shouldn't exit successfully.
+    pub exit_codes: HashMap<String, i32>,
+    /// The lines each task prints just before exiting.
+    pub output: HashMap<String, Vec<String>>,
+    /// Tasks that leave a process in the background holding their output open,
+    /// so that it never closes.
+    pub held_output: HashSet<String>,
+}
+
+/// Everything that happened during a simulation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Every scheduler event, apart from `task.scheduled`.
+    pub records: Vec<Record>,
+    pub peak: Peak,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// The virtual time of the event.
+    pub time: DateTime<Local>,
+    pub event: String,
+    pub task: Option<String>,
+    pub message: String,
+}
+
+/// The most tasks that were running at once.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Peak {
+    pub running: usize,
+    /// When the peak was first reached.
+    pub at: Option<DateTime<Local>>,
+    pub tasks: Vec<String>,
+}
+
+/// Runs the scheduler from `from` until `to` using a virtual clock, with every
+/// task replaced by a stub that exits after its duration.
+pub fn simulate(
+    config: PathBuf,
+    tasks: HashMap<String, ResolvedTask>,
+    concurrency: Concurrency,
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+    durations: Durations,
+) -> eyre::Result<Report> {
+    let clock = VirtualClock::new(from);
+    let stubs = Rc::new(RefCell::new(Stubs {
+        clock: clock.clone(),
+        durations,
+        running: vec![],
+        next_pid: 1,
+        peak: Peak::default(),
+    }));
+    let records = Arc::new(Mutex::new(vec![]));
+    let recorder = Recorder {
+        clock: clock.clone(),
+        records: records.clone(),
+    };
+
+    tracing::subscriber::with_default(Registry::default().with(recorder), || {
+        let mut scheduler = Scheduler::with_runtime(
+            config,
+            tasks,
+            concurrency,
+            Box::new(clock.clone()),
+            Box::new(StubProcesses(stubs.clone())),
+        )?;
+        scheduler.start_on_start();
+
+        loop {
+            let now = clock.now();
+            stubs.borrow_mut().exit_due(now);
+            scheduler.handle_pending();
+            scheduler.tick(now);
+
+            let next = [scheduler.next_deadline(), stubs.borrow().next_exit()]
+                .into_iter()
+                .flatten()
+                .min();
+            match next {
+                Some(next) if next <= to => clock.set(next.max(now)),
+                _ => break,
+            }
+        }
+
+        eyre::Ok(())
+    })?;
+
+    let records = std::mem::take(&mut *records.lock().unwrap_or_else(PoisonError::into_inner));
+    let peak = stubs.borrow().peak.clone();
+    Ok(Report { records, peak })
+}
+
+struct Stubs {
+    clock: VirtualClock,
+    durations: Durations,
+    running: Vec<Stub>,
+    next_pid: u32,
+    peak: Peak,
+}
+
+struct Stub {
+    pid: u32,
+    task: String,
+    run: u64,
+    exit_at: DateTime<Local>,
+    status: ExitStatus,
+    output: Arc<Mutex<Buffer>>,
+    tx: Sender<Event>,
+}
+
+impl Stubs {
+    fn next_exit(&self) -> Option<DateTime<Local>> {
+        self.running.iter().map(|s| s.exit_at).min()
+    }
+
+    /// Sends the events for every stub that should have exited by `now`.
+    fn exit_due(&mut self, now: DateTime<Local>) {
+        let (due, running) = std::mem::take(&mut self.running)
+            .into_iter()
+            .partition(|s| s.exit_at <= now);
+        self.running = running;
+
+        for stub in due {
+            let lines = self.durations.output.get(&stub.task).into_iter().flatten();
+            let mut buffer = stub.output.lock().unwrap_or_else(PoisonError::into_inner);
+            for text in lines {
+                buffer.push(Line {
+                    run: stub.run,
+                    time: stub.exit_at,
+                    stream: Stream::Stdout,
+                    text: text.clone(),
+                });
+            }
+            drop(buffer);
+
+            let _ = stub.tx.send(Event::Exited {
+                task: stub.task.clone(),
+                run: stub.run,
+                status: Ok(stub.status),
+            });
+            if !self.durations.held_output.contains(&stub.task) {
+                let _ = stub.tx.send(Event::OutputClosed {
+                    task: stub.task,
+                    run: stub.run,
+                });
+            }
+        }
+    }
+
+    /// Makes a stub exit straight away.
+    fn exit_now(&mut self, pid: u32, status: ExitStatus) {
+        let now = self.clock.now();
+        if let Some(stub) = self.running.iter_mut().find(|s| s.pid == pid) {
+            stub.exit_at = now;
+            stub.status = status;
+        }
+    }
+}
+
+struct StubProcesses(Rc<RefCell<Stubs>>);
+
+impl Processes for StubProcesses {
+    fn spawn(
+        &mut self,
+        id: &str,
+        run: u64,
+        task: &ResolvedTask,
+        _trigger: &Trigger,
+        output: &Output,
+        tx: Sender<Event>,
+    ) -> eyre::Result<(u32, Vec<String>)> {
+        let argv = task.argv()?;
+        let mut stubs = self.0.borrow_mut();
+        let now = stubs.clock.now();
+        let duration = stubs
+            .durations
+            .tasks
+            .get(id)
+            .copied()
+            .unwrap_or(stubs.durations.default);
+        let code = stubs.durations.exit_codes.get(id).copied().unwrap_or(0);
+
+        let pid = stubs.next_pid;
+        stubs.next_pid += 1;
+        stubs.running.push(Stub {
+            pid,
+            task: id.to_owned(),
+            run,
+            exit_at: now + duration,
+            status: ExitStatus::from_raw(code << 8),
+            output: output.buffer.clone(),
+            tx,
+        });
+
+        if stubs.running.len() > stubs.peak.running {
+            let mut tasks: Vec<_> = stubs.running.iter().map(|s| s.task.clone()).collect();
+            tasks.sort();
+            stubs.peak = Peak {
+                running: stubs.running.len(),
+                at: Some(now),
+                tasks,
+            };
+        }
+
+        Ok((pid, argv))
+    }
+
+    fn spawn_stop(
+        &mut self,
+        id: &str,
+        run: u64,
+        _task: &ResolvedTask,
+        _argv: &[String],
+        wait: bool,
+        tx: Sender<Event>,
+    ) -> io::Result<()> {
+        let mut stubs = self.0.borrow_mut();
+        let pid = stubs
+            .running
+            .iter()
+            .find(|s| s.task == id && s.run == run)
+            .map(|s| s.pid);
+        if let Some(pid) = pid {
+            stubs.exit_now(pid, ExitStatus::from_raw(0));
+        }
+
+        if wait {
+            let _ = tx.send(Event::StopExited {
+                task: id.to_owned(),
+                run,
+                status: Ok(ExitStatus::from_raw(0)),
+            });
+        }
+        Ok(())
+    }
+
+    fn ready(
+        &mut self,
+        id: &str,
+        run: u64,
+        _task: &ResolvedTask,
+        _output: &Output,
+        tx: Sender<Event>,
+        _cancel: Arc<AtomicBool>,
+    ) -> eyre::Result<()> {
+        // Stubs are ready as soon as they start.
+        let _ = tx.send(Event::Ready {
+            task: id.to_owned(),
+            run,
+        });
+        Ok(())
+    }
+
+    fn health(
+        &mut self,
+        _id: &str,
+        _run: u64,
+        _task: &ResolvedTask,
+        _tx: Sender<Event>,
+        _cancel: Arc<AtomicBool>,
+    ) -> eyre::Result<()> {
+        // Stubs are always healthy.
+        Ok(())
+    }
+
+    fn watch(
+        &mut self,
+        _id: &str,
+        _task: &ResolvedTask,
+        _tx: Sender<Event>,
+    ) -> eyre::Result<Vec<Box<dyn Watcher>>> {
+        // Nothing changes during a simulation.
+        Ok(vec![])
+    }
+
+    fn notify(
+        &mut self,
+        _task: &ResolvedTask,
+        _notify: &Notify,
+        _notification: Notification,
+    ) -> eyre::Result<()> {
+        // Notifications are only recorded by their events.
+        Ok(())
+    }
+
+    fn mail(&mut self, _id: &str, _run: u64, _sendmail: Vec<String>, _message: String) {
+        // Emails are only recorded by their events.
+    }
+
+    fn lock(&mut self, _path: &Path) -> io::Result<Option<FileLock>> {
+        // There are no other instances to share the lock with.
+        Ok(Some(FileLock::stub()))
+    }
+
+    fn signal(&mut self, pid: u32, signal: libc::c_int) {
+        self.0
+            .borrow_mut()
+            .exit_now(pid, ExitStatus::from_raw(signal));
+    }
+}
+
+/// Collects scheduler events, stamped with the virtual time.
+struct Recorder {
+    clock: VirtualClock,
+    records: Arc<Mutex<Vec<Record>>>,
+}
+
+impl<S: Subscriber> Layer<S> for Recorder {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        let Some(name) = fields.event.filter(|e| e != "task.scheduled") else {
+            return;
+        };
+        self.records
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Record {
+                time: self.clock.now(),
+                event: name,
+                task: fields.task,
+                message: fields.message,
+            });
+    }
+}
+
+#[derive(Default)]
+struct Fields {
+    event: Option<String>,
+    task: Option<String>,
+    message: String,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "event" => self.event = Some(value.to_owned()),
+            "task" => self.task = Some(value.to_owned()),
+            "message" => value.clone_into(&mut self.message),
+            _ => (),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        }
+    }
+}
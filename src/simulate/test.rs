@@ -0,0 +1,661 @@
+use chrono::TimeZone;
+use pretty_assertions::assert_eq;
+
+use super::*;
+use crate::config::Config;
+
+fn at(min: u32, sec: u32) -> DateTime<Local> {
+    Local.with_ymd_and_hms(2024, 3, 1, 10, min, sec).unwrap()
+}
+
+fn secs(secs: i64) -> TimeDelta {
+    TimeDelta::try_seconds(secs).unwrap()
+}
+
+fn run(config: &str, to: DateTime<Local>, durations: Durations) -> Report {
+    let config = config.parse::<Config>().unwrap();
+    let concurrency = config.concurrency();
+    let (_, tasks) = config.try_into().unwrap();
+    simulate(
+        PathBuf::from("servum.toml"),
+        tasks,
+        concurrency,
+        at(0, 0),
+        to,
+        durations,
+    )
+    .unwrap()
+}
+
+fn events(report: &Report) -> Vec<(DateTime<Local>, &str, &str)> {
+    report
+        .records
+        .iter()
+        .map(|r| {
+            (
+                r.time,
+                r.event.as_str(),
+                r.task.as_deref().unwrap_or_default(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_simulate_overlap() {
+    let report = run(
+        "
+        [task.slow]
+        cron = '0 * * * * *'
+        cmd = 'sleep 90'
+        ",
+        at(3, 0),
+        Durations {
+            tasks: HashMap::from([("slow".to_owned(), secs(90))]),
+            default: secs(1),
+            ..Durations::default()
+        },
+    );
+
+    assert_eq!(
+        events(&report),
+        [
+            (at(1, 0), "task.started", "slow"),
+            (at(2, 0), "task.skipped", "slow"),
+            (at(2, 30), "task.finished", "slow"),
+            (at(3, 0), "task.started", "slow"),
+        ]
+    );
+}
+
+#[test]
+fn test_simulate_peak() {
+    let report = run(
+        "
+        [task.server]
+        cmd = 'server'
+        on-start = true
+
+        [task.a]
+        cron = '0 * * * * *'
+        cmd = 'true'
+
+        [task.b]
+        cron = '30 * * * * *'
+        cmd = 'true'
+        ",
+        at(2, 0),
+        Durations {
+            tasks: HashMap::from([("server".to_owned(), secs(86_400))]),
+            default: secs(45),
+            ..Durations::default()
+        },
+    );
+
+    assert_eq!(
+        report.peak,
+        Peak {
+            running: 3,
+            at: Some(at(1, 0)),
+            tasks: vec!["a".to_owned(), "b".to_owned(), "server".to_owned()],
+        }
+    );
+}
+
+#[test]
+fn test_simulate_requires() {
+    let report = run(
+        "
+        [task.app]
+        cmd = 'app'
+        on-start = true
+        requires = ['proxy']
+
+        [task.proxy]
+        cmd = 'proxy'
+        on-start = true
+
+        [task.report]
+        cron = '0 * * * * *'
+        cmd = 'report'
+        requires = ['backup']
+
+        [task.backup]
+        cmd = 'backup'
+        ",
+        at(1, 0),
+        Durations {
+            tasks: HashMap::new(),
+            default: secs(3600),
+            ..Durations::default()
+        },
+    );
+
+    assert_eq!(
+        events(&report),
+        [
+            (at(0, 0), "task.started", "proxy"),
+            (at(0, 0), "task.started", "app"),
+            (at(1, 0), "task.skipped", "report"),
+        ]
+    );
+}
+
+#[test]
+fn test_simulate_ready() {
+    let report = run(
+        "
+        [task.app]
+        cmd = 'app'
+        on-start = true
+        requires = ['db']
+
+        [task.db]
+        cmd = 'db'
+        on-start = true
+        ready.file = '/run/db.pid'
+        ",
+        at(1, 0),
+        Durations {
+            tasks: HashMap::new(),
+            default: secs(3600),
+            ..Durations::default()
+        },
+    );
+
+    assert_eq!(
+        events(&report),
+        [
+            (at(0, 0), "task.started", "db"),
+            (at(0, 0), "task.waiting", "app"),
+            (at(0, 0), "task.ready", "db"),
+            (at(0, 0), "task.started", "app"),
+        ]
+    );
+}
+
+#[test]
+fn test_simulate_triggers() {
+    let report = run(
+        "
+        [task.backup]
+        cmd = 'backup'
+        on-start = true
+        on-success = ['verify']
+
+        [task.verify]
+        cmd = 'verify'
+        on-success = ['prune']
+        on-complete = ['report']
+
+        [task.prune]
+        cmd = 'prune'
+
+        [task.report]
+        cmd = 'report'
+        ",
+        at(2, 0),
+        Durations {
+            tasks: HashMap::from([("backup".to_owned(), secs(60))]),
+            default: secs(1),
+            ..Durations::default()
+        },
+    );
+
+    assert_eq!(
+        events(&report),
+        [
+            (at(0, 0), "task.started", "backup"),
+            (at(1, 0), "task.finished", "backup"),
+            (at(1, 0), "task.started", "verify"),
+            (at(1, 1), "task.finished", "verify"),
+            (at(1, 1), "task.started", "prune"),
+            (at(1, 1), "task.started", "report"),
+            (at(1, 2), "task.finished", "prune"),
+            (at(1, 2), "task.finished", "report"),
+        ]
+    );
+}
+
+#[test]
+fn test_simulate_limits() {
+    let report = run(
+        "
+        max-concurrent = 1
+
+        [task.a]
+        cron = '0 * * * * *'
+        cmd = 'a'
+
+        [task.b]
+        cron = '0 * * * * *'
+        cmd = 'b'
+        priority = 5
+
+        [task.c]
+        cron = '0 * * * * *'
+        cmd = 'c'
+        queue-timeout = 30000
+        ",
+        at(1, 50),
+        Durations {
+            tasks: HashMap::new(),
+            default: secs(20),
+            ..Durations::default()
+        },
+    );
+
+    assert_eq!(
+        events(&report),
+        [
+            (at(1, 0), "task.started", "a"),
+            (at(1, 0), "task.queued", "b"),
+            (at(1, 0), "task.queued", "c"),
+            (at(1, 20), "task.finished", "a"),
+            (at(1, 20), "task.started", "b"),
+            (at(1, 30), "task.skipped", "c"),
+            (at(1, 40), "task.finished", "b"),
+        ]
+    );
+}
+
+#[test]
+fn test_simulate_queue_before_triggers() {
+    let report = run(
+        "
+        max-concurrent = 1
+
+        [task.backup]
+        cmd = 'backup'
+        on-start = true
+        on-success = ['verify']
+
+        [task.verify]
+        cmd = 'verify'
+
+        [task.urgent]
+        cron = '30 * * * * *'
+        cmd = 'urgent'
+        priority = 5
+        ",
+        at(1, 10),
+        Durations {
+            tasks: HashMap::from([("backup".to_owned(), secs(60))]),
+            default: secs(1),
+            ..Durations::default()
+        },
+    );
+
+    // `urgent` has been waiting for the slot, so it gets it before `verify`.
+    assert_eq!(
+        events(&report),
+        [
+            (at(0, 0), "task.started", "backup"),
+            (at(0, 30), "task.queued", "urgent"),
+            (at(1, 0), "task.finished", "backup"),
+            (at(1, 0), "task.started", "urgent"),
+            (at(1, 0), "task.queued", "verify"),
+            (at(1, 1), "task.finished", "urgent"),
+            (at(1, 1), "task.started", "verify"),
+            (at(1, 2), "task.finished", "verify"),
+        ]
+    );
+}
+
+#[test]
+fn test_simulate_retry() {
+    let report = run(
+        "
+        [task.flaky]
+        cmd = 'flaky'
+        on-start = true
+        retry = { attempts = 2, backoff = '10s' }
+        ",
+        at(2, 0),
+        Durations {
+            tasks: HashMap::new(),
+            default: secs(1),
+            exit_codes: HashMap::from([("flaky".to_owned(), 1)]),
+            ..Durations::default()
+        },
+    );
+
+    assert_eq!(
+        events(&report),
+        [
+            (at(0, 0), "task.started", "flaky"),
+            (at(0, 1), "task.finished", "flaky"),
+            (at(0, 1), "task.retrying", "flaky"),
+            (at(0, 11), "task.started", "flaky"),
+            (at(0, 12), "task.finished", "flaky"),
+            (at(0, 12), "task.retrying", "flaky"),
+            (at(0, 32), "task.started", "flaky"),
+            (at(0, 33), "task.finished", "flaky"),
+        ]
+    );
+}
+
+#[test]
+fn test_simulate_retry_cancelled() {
+    let report = run(
+        "
+        max-concurrent = 1
+
+        [task.flaky]
+        cron = '20 * * * * *'
+        cmd = 'flaky'
+        on-start = true
+        retry = { attempts = 1, backoff = '30s' }
+
+        [task.slow]
+        cron = '10 * * * * *'
+        cmd = 'slow'
+        ",
+        at(0, 40),
+        Durations {
+            tasks: HashMap::from([("slow".to_owned(), secs(15))]),
+            default: secs(1),
+            exit_codes: HashMap::from([("flaky".to_owned(), 1)]),
+            ..Durations::default()
+        },
+    );
+
+    // The retry is still pending while the cron start is queued, and is only
+    // replaced once that start gets to run.
+    assert_eq!(
+        events(&report),
+        [
+            (at(0, 0), "task.started", "flaky"),
+            (at(0, 1), "task.finished", "flaky"),
+            (at(0, 1), "task.retrying", "flaky"),
+            (at(0, 10), "task.started", "slow"),
+            (at(0, 20), "task.queued", "flaky"),
+            (at(0, 25), "task.finished", "slow"),
+            (at(0, 25), "task.retry_cancelled", "flaky"),
+            (at(0, 25), "task.started", "flaky"),
+            (at(0, 26), "task.finished", "flaky"),
+            (at(0, 26), "task.retrying", "flaky"),
+        ]
+    );
+}
+
+#[test]
+fn test_simulate_output() {
+    let report = run(
+        "
+        [task.check]
+        cmd = 'check'
+        on-start = true
+        fail-on-output = 'ERROR'
+        retry.attempts = 1
+
+        [task.probe]
+        cmd = 'probe'
+        on-start = true
+        succeed-on-output = '^ok$'
+        retry.attempts = 1
+
+        [task.silent]
+        cmd = 'silent'
+        on-start = true
+        succeed-on-output = '^ok$'
+        retry.attempts = 1
+        ",
+        at(0, 10),
+        Durations {
+            default: secs(1),
+            output: HashMap::from([
+                ("check".to_owned(), vec!["ERROR: disk full".to_owned()]),
+                ("probe".to_owned(), vec!["ok".to_owned()]),
+            ]),
+            ..Durations::default()
+        },
+    );
+
+    // Only the runs whose output failed them are retried.
+    assert_eq!(
+        events(&report),
+        [
+            (at(0, 0), "task.started", "check"),
+            (at(0, 0), "task.started", "probe"),
+            (at(0, 0), "task.started", "silent"),
+            (at(0, 1), "task.finished", "check"),
+            (at(0, 1), "task.retrying", "check"),
+            (at(0, 1), "task.finished", "probe"),
+            (at(0, 1), "task.finished", "silent"),
+            (at(0, 1), "task.retrying", "silent"),
+        ]
+    );
+}
+
+#[test]
+fn test_simulate_held_output() {
+    let report = run(
+        "
+        [task.daemonize]
+        cron = '10 * * * * *'
+        cmd = 'daemonize'
+        on-start = true
+        fail-on-output = 'ERROR'
+        ",
+        at(0, 15),
+        Durations {
+            default: secs(1),
+            held_output: HashSet::from(["daemonize".to_owned()]),
+            ..Durations::default()
+        },
+    );
+
+    // The output never closes, so the run finishes once it has had long enough,
+    // and doesn't hold up the next one.
+    assert_eq!(
+        events(&report),
+        [
+            (at(0, 0), "task.started", "daemonize"),
+            (at(0, 6), "task.output_timeout", "daemonize"),
+            (at(0, 6), "task.finished", "daemonize"),
+            (at(0, 10), "task.started", "daemonize"),
+        ]
+    );
+}
+
+#[test]
+fn test_simulate_stopped_held_output() {
+    let report = run(
+        "
+        [task.app]
+        cmd = 'app'
+        on-start = true
+        requires = ['db']
+        requirement-failure = 'stop'
+        stop-timeout = 1000
+        fail-on-output = 'ERROR'
+
+        [task.db]
+        cmd = 'db'
+        on-start = true
+        ",
+        at(0, 30),
+        Durations {
+            tasks: HashMap::from([("db".to_owned(), secs(10))]),
+            default: secs(3600),
+            exit_codes: HashMap::from([("db".to_owned(), 1)]),
+            held_output: HashSet::from(["app".to_owned()]),
+            ..Durations::default()
+        },
+    );
+
+    // `app` exits as soon as it is asked to stop, so it isn't killed while it
+    // waits for its output.
+    assert_eq!(
+        events(&report),
+        [
+            (at(0, 0), "task.started", "db"),
+            (at(0, 0), "task.started", "app"),
+            (at(0, 10), "task.finished", "db"),
+            (at(0, 10), "task.requirement_failed", "app"),
+            (at(0, 10), "task.stop_requested", "app"),
+            (at(0, 15), "task.output_timeout", "app"),
+            (at(0, 15), "task.finished", "app"),
+        ]
+    );
+}
+
+#[test]
+fn test_simulate_lock() {
+    let report = run(
+        "
+        [task.backup]
+        cron = '0 * * * * *'
+        cmd = 'backup'
+        lock = 'db-maintenance'
+
+        [task.migrate]
+        cron = '30 * * * * *'
+        cmd = 'migrate'
+        lock = 'db-maintenance'
+
+        [task.report]
+        cron = '30 * * * * *'
+        cmd = 'report'
+        ",
+        at(1, 20),
+        Durations {
+            tasks: HashMap::new(),
+            default: secs(45),
+            ..Durations::default()
+        },
+    );
+
+    assert_eq!(
+        events(&report),
+        [
+            (at(0, 30), "task.started", "migrate"),
+            (at(0, 30), "task.started", "report"),
+            (at(1, 0), "task.queued", "backup"),
+            (at(1, 15), "task.finished", "migrate"),
+            (at(1, 15), "task.started", "backup"),
+            (at(1, 15), "task.finished", "report"),
+        ]
+    );
+}
+
+#[test]
+fn test_simulate_notify() {
+    let report = run(
+        "
+        max-concurrent = 1
+
+        [task.slow]
+        cron = '0 * * * * *'
+        cmd = 'slow'
+
+        [task.report]
+        cron = '10 * * * * *'
+        cmd = 'report'
+        queue-timeout = 5000
+
+        [[task.report.notify]]
+        cmd = 'alert'
+        on = ['timeout']
+        rate-limit = '90s'
+        ",
+        at(2, 20),
+        Durations {
+            tasks: HashMap::from([("slow".to_owned(), secs(50))]),
+            default: secs(1),
+            ..Durations::default()
+        },
+    );
+
+    assert_eq!(
+        events(&report),
+        [
+            (at(0, 10), "task.started", "report"),
+            (at(0, 11), "task.finished", "report"),
+            (at(1, 0), "task.started", "slow"),
+            (at(1, 10), "task.queued", "report"),
+            (at(1, 15), "task.skipped", "report"),
+            (at(1, 15), "notify.sent", "report"),
+            (at(1, 50), "task.finished", "slow"),
+            (at(2, 0), "task.started", "slow"),
+            (at(2, 10), "task.queued", "report"),
+            (at(2, 15), "task.skipped", "report"),
+            (at(2, 15), "notify.suppressed", "report"),
+        ]
+    );
+}
+
+#[test]
+fn test_simulate_notify_held_output() {
+    let report = run(
+        "
+        [task.backup]
+        cmd = 'backup'
+        on-start = true
+        mail-to = 'ops@example.com'
+
+        [[task.backup.notify]]
+        cmd = 'alert'
+        on = ['failure']
+        ",
+        at(0, 30),
+        Durations {
+            default: secs(1),
+            exit_codes: HashMap::from([("backup".to_owned(), 1)]),
+            output: HashMap::from([("backup".to_owned(), vec!["disk full".to_owned()])]),
+            held_output: HashSet::from(["backup".to_owned()]),
+            ..Durations::default()
+        },
+    );
+
+    // The notification and email go out once the run stops waiting for its output.
+    assert_eq!(
+        events(&report),
+        [
+            (at(0, 0), "task.started", "backup"),
+            (at(0, 6), "task.output_timeout", "backup"),
+            (at(0, 6), "task.finished", "backup"),
+            (at(0, 6), "notify.sent", "backup"),
+            (at(0, 6), "mail.sent", "backup"),
+        ]
+    );
+}
+
+#[test]
+fn test_simulate_overdue() {
+    let report = run(
+        "
+        [task.backup]
+        cron = '0 * * * * *'
+        cmd = 'backup'
+        enabled = false
+        expect-success-within = '90s'
+
+        [[task.backup.notify]]
+        cmd = 'alert'
+
+        [task.report]
+        cron = '0 * * * * *'
+        cmd = 'report'
+        expect-success-within = '90s'
+        ",
+        at(4, 0),
+        Durations {
+            default: secs(1),
+            ..Durations::default()
+        },
+    );
+
+    let overdue: Vec<_> = events(&report)
+        .into_iter()
+        .filter(|(_, _, task)| *task == "backup")
+        .collect();
+    assert_eq!(
+        overdue,
+        [
+            (at(1, 30), "task.overdue", "backup"),
+            (at(1, 30), "notify.sent", "backup"),
+        ]
+    );
+}
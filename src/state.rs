@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod test;
+
+use std::{fs, io, path::Path};
+
+use chrono::{DateTime, Local};
+use color_eyre::eyre::{self, WrapErr};
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+/// State that outlives servum, kept as JSON in the `state-file`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct State {
+    /// Tasks with `expect-success-within`, by id.
+    pub tasks: HashMap<String, TaskState>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TaskState {
+    /// When servum first saw the task, which is where the window starts if it
+    /// has never succeeded.
+    pub since: DateTime<Local>,
+    pub last_success: Option<DateTime<Local>>,
+}
+
+impl State {
+    /// Reads the state from `path`, which is empty if the file doesn't exist yet.
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err)
+                    .wrap_err_with(|| format!("Failed to read state `{}`", path.display()))
+            }
+        };
+        serde_json::from_str(&contents)
+            .wrap_err_with(|| format!("Failed to parse state `{}`", path.display()))
+    }
+
+    /// Writes the state to `path`, replacing the old file in one step so that
+    /// it is never left half-written.
+    pub fn save(&self, path: &Path) -> eyre::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, json)
+            .and_then(|()| fs::rename(&tmp, path))
+            .wrap_err_with(|| format!("Failed to write state `{}`", path.display()))
+    }
+}
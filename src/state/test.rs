@@ -0,0 +1,37 @@
+use chrono::TimeZone;
+use map_macro::hashbrown::hash_map;
+use pretty_assertions::assert_eq;
+
+use super::*;
+
+#[test]
+fn test_load_missing() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = State::load(&dir.path().join("servum.state")).unwrap();
+    assert_eq!(state, State::default());
+}
+
+#[test]
+fn test_save_load() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("servum.state");
+    let state = State {
+        tasks: hash_map! {
+            "backup".to_owned() => TaskState {
+                since: Local.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap(),
+                last_success: Some(Local.with_ymd_and_hms(2024, 3, 2, 3, 0, 0).unwrap()),
+            },
+            "report".to_owned() => TaskState {
+                since: Local.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap(),
+                last_success: None,
+            },
+        },
+    };
+
+    state.save(&path).unwrap();
+    assert_eq!(State::load(&path).unwrap(), state);
+    assert!(!dir.path().join("servum.state.tmp").exists());
+
+    fs::write(&path, "not json").unwrap();
+    assert!(State::load(&path).is_err());
+}
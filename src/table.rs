@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod test;
+
+use std::fmt::Write;
+
+/// A simple table that aligns its columns when printed.
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(header: &[&str]) -> Self {
+        Self {
+            rows: vec![header.iter().map(|h| (*h).to_owned()).collect()],
+        }
+    }
+
+    pub fn row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    pub fn render(&self) -> String {
+        let columns = self.rows.iter().map(Vec::len).max().unwrap_or_default();
+        let widths: Vec<_> = (0..columns)
+            .map(|c| {
+                self.rows
+                    .iter()
+                    .filter_map(|r| r.get(c))
+                    .map(|v| v.chars().count())
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        let mut out = String::new();
+        for row in &self.rows {
+            let mut line = String::new();
+            for (value, width) in row.iter().zip(&widths) {
+                let _ = write!(line, "{value:width$}  ");
+            }
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+/// Formats a duration in a short, human readable way, e.g. `1h5m` or `2.5s`.
+pub fn duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    match secs {
+        0..60 => format!("{}.{}s", secs, (ms % 1000) / 100),
+        60..3600 => format!("{}m{}s", secs / 60, secs % 60),
+        3600..86400 => format!("{}h{}m", secs / 3600, (secs % 3600) / 60),
+        _ => format!("{}d{}h", secs / 86400, (secs % 86400) / 3600),
+    }
+}
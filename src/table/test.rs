@@ -0,0 +1,27 @@
+use pretty_assertions::assert_eq;
+
+use super::*;
+
+#[test]
+fn test_render() {
+    let mut table = Table::new(&["TASK", "STATE", "PID"]);
+    table.row(vec![
+        "backup".to_owned(),
+        "running".to_owned(),
+        "42".to_owned(),
+    ]);
+    table.row(vec!["db".to_owned(), "idle".to_owned(), String::new()]);
+
+    assert_eq!(
+        table.render(),
+        "TASK    STATE    PID\nbackup  running  42\ndb      idle\n"
+    );
+}
+
+#[test]
+fn test_duration() {
+    assert_eq!(duration(2_540), "2.5s");
+    assert_eq!(duration(65_000), "1m5s");
+    assert_eq!(duration(3_900_000), "1h5m");
+    assert_eq!(duration(90_000_000), "1d1h");
+}
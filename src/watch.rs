@@ -0,0 +1,310 @@
+#[cfg(test)]
+mod test;
+
+use std::{
+    collections::BTreeSet,
+    env,
+    path::{Component, Path, PathBuf},
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+
+use color_eyre::eyre::{self, WrapErr};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::{gitignore::Gitignore, Match};
+use notify::{
+    event::{ModifyKind, RenameMode},
+    EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher,
+};
+
+use crate::{
+    config::{FsEvent, TaskConfig, Watch},
+    scheduler::Event,
+};
+
+/// How long to wait for changes to stop before reloading.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watches the config file for changes, sending the new config to the scheduler.
+///
+/// The returned watcher must be kept alive for as long as changes should be picked up.
+pub fn config(
+    path: &Path,
+    watch: &Watch,
+    tx: Sender<Event>,
+) -> eyre::Result<Option<Box<dyn Watcher>>> {
+    if !watch.enabled {
+        return Ok(None);
+    }
+
+    let file = path
+        .canonicalize()
+        .wrap_err_with(|| format!("Failed to find config `{}`", path.display()))?;
+    let dir = file.parent().map(ToOwned::to_owned).unwrap_or_default();
+
+    let (changed, rx) = mpsc::channel();
+    let handler = move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if !event.kind.is_access() && event.paths.contains(&file) {
+            let _ = changed.send(());
+        }
+    };
+
+    thread::spawn(move || {
+        while rx.recv().is_ok() {
+            // Editors often write files in multiple steps, so wait for things to settle.
+            while rx.recv_timeout(DEBOUNCE).is_ok() {}
+
+            if tx.send(Event::Reload).is_err() {
+                return;
+            }
+        }
+    });
+
+    // Watch the directory rather than the file itself, as editors often replace the file.
+    let mut watcher = watcher(handler, watch.force_poll)?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+    Ok(Some(watcher))
+}
+
+/// Decides which changes to paths are relevant to a task.
+#[derive(Debug)]
+pub struct Matcher {
+    globs: GlobSet,
+    events: Vec<FsEvent>,
+    /// The directories that need watching to see every matching path.
+    roots: Vec<PathBuf>,
+    /// Whether to skip paths ignored by `.gitignore` files.
+    gitignore: bool,
+}
+
+impl Matcher {
+    /// Returns `None` if there are no patterns to watch.
+    pub fn new(patterns: &[String], events: &[FsEvent], cwd: &Path) -> eyre::Result<Option<Self>> {
+        if patterns.is_empty() {
+            return Ok(None);
+        }
+
+        let mut globs = GlobSetBuilder::new();
+        let mut roots = vec![];
+        for pattern in patterns {
+            let pattern = cwd.join(pattern);
+            let glob = GlobBuilder::new(&pattern.to_string_lossy())
+                .literal_separator(true)
+                .build()
+                .wrap_err_with(|| format!("Invalid watch path `{}`", pattern.display()))?;
+            globs.add(glob);
+
+            let root = root(&pattern);
+            // Nested roots are already covered, as every root is watched recursively.
+            if !roots.iter().any(|r| root.starts_with(r)) {
+                roots.retain(|r: &PathBuf| !r.starts_with(&root));
+                roots.push(root);
+            }
+        }
+
+        Ok(Some(Self {
+            globs: globs.build()?,
+            events: events.to_vec(),
+            roots,
+            gitignore: false,
+        }))
+    }
+
+    /// Skips paths that are ignored by a `.gitignore` in any directory above
+    /// them, like git does, along with anything in `.git`.
+    pub fn with_gitignore(mut self) -> Self {
+        self.gitignore = true;
+        self
+    }
+
+    pub fn matches(&self, kind: FsEvent, path: &Path) -> bool {
+        self.events.contains(&kind) && self.globs.is_match(path) && !self.ignored(path)
+    }
+
+    fn ignored(&self, path: &Path) -> bool {
+        if !self.gitignore {
+            return false;
+        }
+        if path.components().any(|c| c.as_os_str() == ".git") {
+            return true;
+        }
+
+        // The files are read each time so that edits to them are picked up, and
+        // the closest one with an opinion about the path wins.
+        let is_dir = path.is_dir();
+        for dir in path.ancestors().skip(1) {
+            let file = dir.join(".gitignore");
+            if !file.is_file() {
+                continue;
+            }
+            // Broken lines are skipped rather than failing the whole file.
+            match Gitignore::new(file)
+                .0
+                .matched_path_or_any_parents(path, is_dir)
+            {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+}
+
+/// The directory a glob starts in, which is everything before the first
+/// component containing a wildcard.
+fn root(pattern: &Path) -> PathBuf {
+    let mut root = PathBuf::new();
+    for component in pattern.components() {
+        if let Component::Normal(part) = component {
+            if part.to_string_lossy().contains(['*', '?', '[', '{']) {
+                return root;
+            }
+        }
+        root.push(component);
+    }
+
+    // Without any wildcards the pattern is a single path, so watch its directory.
+    root.parent().map(ToOwned::to_owned).unwrap_or(root)
+}
+
+/// The kinds of change that a notify event represents, along with the path
+/// each applies to.
+fn changes(event: &notify::Event) -> Vec<(FsEvent, &Path)> {
+    let paths = event.paths.iter().map(PathBuf::as_path);
+    match event.kind {
+        EventKind::Create(_) => paths.map(|p| (FsEvent::Create, p)).collect(),
+        EventKind::Remove(_) => paths.map(|p| (FsEvent::Remove, p)).collect(),
+        // Renames are treated as the old path being removed and the new one created.
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            paths.map(|p| (FsEvent::Remove, p)).collect()
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            paths.map(|p| (FsEvent::Create, p)).collect()
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => paths
+            .zip([FsEvent::Remove, FsEvent::Create])
+            .map(|(p, kind)| (kind, p))
+            .collect(),
+        EventKind::Modify(_) => paths.map(|p| (FsEvent::Modify, p)).collect(),
+        EventKind::Access(_) | EventKind::Any | EventKind::Other => vec![],
+    }
+}
+
+/// Watches the `watch-paths` and `restart-on-change` paths of a task, sending
+/// [`Event::Changed`] and [`Event::SourcesChanged`] once changes to matching
+/// paths have settled.
+///
+/// The returned watchers must be kept alive for as long as changes should be picked up.
+pub fn task(
+    id: &str,
+    task: &TaskConfig,
+    force_poll: bool,
+    tx: &Sender<Event>,
+) -> eyre::Result<Vec<Box<dyn Watcher>>> {
+    let cwd = env::current_dir()?;
+    let debounce = Duration::from_millis(task.watch_debounce as u64);
+    let mut watchers = vec![];
+
+    if let Some(matcher) = Matcher::new(&task.watch_paths, &task.watch_events, &cwd)? {
+        let id = id.to_owned();
+        watchers.push(watch(
+            matcher,
+            debounce,
+            force_poll,
+            tx.clone(),
+            move |paths| Event::Changed {
+                task: id.clone(),
+                paths,
+            },
+        )?);
+    }
+
+    let sources = Matcher::new(&task.restart_on_change, &FsEvent::ALL, &cwd)?;
+    if let Some(matcher) = sources {
+        let id = id.to_owned();
+        watchers.push(watch(
+            matcher.with_gitignore(),
+            debounce,
+            force_poll,
+            tx.clone(),
+            move |paths| Event::SourcesChanged {
+                task: id.clone(),
+                paths,
+            },
+        )?);
+    }
+
+    Ok(watchers)
+}
+
+/// Watches the roots of `matcher`, sending the event made by `event` with every
+/// matching path once they stop changing for `debounce`.
+fn watch(
+    matcher: Matcher,
+    debounce: Duration,
+    force_poll: bool,
+    tx: Sender<Event>,
+    event: impl Fn(Vec<PathBuf>) -> Event + Send + 'static,
+) -> eyre::Result<Box<dyn Watcher>> {
+    let roots = matcher.roots().to_vec();
+
+    let (changed, rx) = mpsc::channel();
+    let handler = move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        for (kind, path) in changes(&event) {
+            if matcher.matches(kind, path) {
+                let _ = changed.send(path.to_owned());
+            }
+        }
+    };
+
+    thread::spawn(move || {
+        // Stops once the watcher (and so the handler) has been dropped.
+        while let Ok(path) = rx.recv() {
+            let mut paths = BTreeSet::from([path]);
+            while let Ok(path) = rx.recv_timeout(debounce) {
+                paths.insert(path);
+            }
+
+            if tx.send(event(paths.into_iter().collect())).is_err() {
+                return;
+            }
+        }
+    });
+
+    let mut watcher = watcher(handler, force_poll)?;
+    for root in &roots {
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .wrap_err_with(|| format!("Failed to watch `{}`", root.display()))?;
+    }
+
+    Ok(watcher)
+}
+
+/// The platform's watcher, or one that polls if `force_poll` is set.
+fn watcher(
+    handler: impl notify::EventHandler,
+    force_poll: bool,
+) -> notify::Result<Box<dyn Watcher>> {
+    Ok(if force_poll {
+        Box::new(PollWatcher::new(
+            handler,
+            notify::Config::default().with_poll_interval(Duration::from_secs(2)),
+        )?)
+    } else {
+        Box::new(RecommendedWatcher::new(handler, notify::Config::default())?)
+    })
+}
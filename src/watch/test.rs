@@ -0,0 +1,89 @@
+use std::fs;
+
+use pretty_assertions::assert_eq;
+
+use super::*;
+
+fn matcher(paths: &[&str], events: &[FsEvent]) -> Matcher {
+    let paths: Vec<_> = paths.iter().map(ToString::to_string).collect();
+    Matcher::new(&paths, events, Path::new("/srv"))
+        .unwrap()
+        .unwrap()
+}
+
+#[test]
+fn test_matcher_roots() {
+    let m = matcher(
+        &[
+            "incoming/**/*.csv",
+            "/data/*/report.json",
+            "incoming/new/*.tsv",
+        ],
+        &[],
+    );
+
+    assert_eq!(
+        m.roots(),
+        [PathBuf::from("/srv/incoming"), PathBuf::from("/data")]
+    );
+
+    // Watching `/srv` covers everything in `/srv/incoming`.
+    let m = matcher(&["incoming/**/*.csv", "config.toml"], &[]);
+    assert_eq!(m.roots(), [PathBuf::from("/srv")]);
+}
+
+#[test]
+fn test_matcher_matches() {
+    let m = matcher(&["incoming/*.csv"], &[FsEvent::Create, FsEvent::Modify]);
+
+    assert!(m.matches(FsEvent::Create, Path::new("/srv/incoming/a.csv")));
+    assert!(m.matches(FsEvent::Modify, Path::new("/srv/incoming/b.csv")));
+    assert!(!m.matches(FsEvent::Remove, Path::new("/srv/incoming/a.csv")));
+    assert!(!m.matches(FsEvent::Create, Path::new("/srv/incoming/a.tsv")));
+    assert!(!m.matches(FsEvent::Create, Path::new("/srv/incoming/old/a.csv")));
+
+    let m = matcher(&["incoming/**/*.csv"], &[FsEvent::Create]);
+    assert!(m.matches(FsEvent::Create, Path::new("/srv/incoming/old/a.csv")));
+}
+
+#[test]
+fn test_matcher_gitignore() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+    fs::create_dir_all(root.join("src/target")).unwrap();
+
+    let m = Matcher::new(&["src/**".to_owned()], &FsEvent::ALL, &root)
+        .unwrap()
+        .unwrap()
+        .with_gitignore();
+
+    assert!(m.matches(FsEvent::Modify, &root.join("src/main.rs")));
+    assert!(!m.matches(FsEvent::Modify, &root.join("src/debug.log")));
+    assert!(!m.matches(FsEvent::Create, &root.join("src/target/out.o")));
+    assert!(!m.matches(FsEvent::Modify, &root.join("src/.git/index")));
+}
+
+#[test]
+fn test_matcher_nested_gitignore() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::create_dir_all(root.join("src/gen")).unwrap();
+    fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+    fs::write(root.join("src/.gitignore"), "gen/\n!keep.log\n").unwrap();
+
+    let m = Matcher::new(&["**".to_owned()], &FsEvent::ALL, &root)
+        .unwrap()
+        .unwrap()
+        .with_gitignore();
+
+    assert!(m.matches(FsEvent::Modify, &root.join("src/main.rs")));
+    assert!(!m.matches(FsEvent::Modify, &root.join("src/gen/out.rs")));
+    assert!(!m.matches(FsEvent::Modify, &root.join("src/debug.log")));
+    assert!(m.matches(FsEvent::Modify, &root.join("src/keep.log")));
+    assert!(!m.matches(FsEvent::Modify, &root.join("debug.log")));
+
+    // Changes to the files are picked up straight away.
+    fs::write(root.join("src/.gitignore"), "").unwrap();
+    assert!(m.matches(FsEvent::Modify, &root.join("src/gen/out.rs")));
+}